@@ -0,0 +1,114 @@
+use std::collections::VecDeque;
+use std::time;
+
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::audit;
+use crate::glot_run::file;
+use crate::glot_run::util;
+
+
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    events: Vec<audit::Event>,
+    total: usize,
+    offset: usize,
+    limit: usize,
+}
+
+
+pub fn handle(config: &config::Config, request: &mut tiny_http::Request) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let query_params = api::get_query_params(request);
+    let from = parse_time_param(&query_params, "from")?;
+    let to = parse_time_param(&query_params, "to")?;
+    let offset = parse_number_param(&query_params, "offset")?.unwrap_or(0);
+    let limit = parse_number_param(&query_params, "limit")?.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+
+    // The log is oldest first, so only the newest offset + limit matching events are kept while reading it
+    let window = offset.saturating_add(limit);
+    let mut newest = VecDeque::new();
+    let mut total = 0;
+
+    let data_root = config.server.data_root.read().unwrap();
+    file::for_each_json_line(&data_root.audit_log_path(), |event: audit::Event| {
+        if audit::is_within(&event, from, to) {
+            total += 1;
+            newest.push_back(event);
+
+            if newest.len() > window {
+                newest.pop_front();
+            }
+        }
+
+        true
+    }).map_err(handle_read_error)?;
+
+    // Unlock datastore
+    drop(data_root);
+
+    // Newest events first
+    let events = newest.into_iter()
+        .rev()
+        .skip(offset)
+        .map(audit::redact_event)
+        .collect();
+
+    api::prepare_json_response(&Response{
+        events,
+        total,
+        offset,
+        limit,
+    })
+}
+
+// Supported query params: from (inclusive) and to (exclusive) as rfc3339 timestamps
+fn parse_time_param(query_params: &[(String, String)], name: &str) -> Result<Option<time::SystemTime>, api::ErrorResponse> {
+    query_params.iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| util::parse_rfc3339(value))
+        .transpose()
+        .map_err(|err| handle_time_param_error(name, err))
+}
+
+fn parse_number_param(query_params: &[(String, String)], name: &str) -> Result<Option<usize>, api::ErrorResponse> {
+    query_params.iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.parse::<usize>())
+        .transpose()
+        .map_err(|err| api::ErrorResponse{
+            status_code: 400,
+            headers: vec![],
+            body: api::ErrorBody{
+                error: format!("request.{}", name),
+                message: format!("Invalid number: {}", err),
+            }
+        })
+}
+
+fn handle_time_param_error(name: &str, err: chrono::ParseError) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 400,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: format!("request.{}", name),
+            message: format!("Invalid rfc3339 timestamp: {}", err),
+        }
+    }
+}
+
+fn handle_read_error(err: file::ReadJsonError) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 500,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "datastore".to_string(),
+            message: err.to_string(),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::credential;
+use crate::glot_run::datastore;
+use crate::glot_run::audit;
+use crate::glot_run::token;
+
+
+#[derive(Debug, serde::Deserialize)]
+struct RequestBody {
+    // A token is generated when it is not given
+    token: Option<ascii::AsciiString>,
+    #[serde(flatten)]
+    credential: credential::CredentialData,
+}
+
+// The plaintext token is only returned when the credential is created
+#[derive(Debug, serde::Serialize)]
+struct Response<'a> {
+    #[serde(flatten)]
+    credential: credential::PublicCredential<'a>,
+    token: &'a ascii::AsciiString,
+}
+
+
+pub fn handle(config: &config::Config, request: &mut tiny_http::Request, actor: &credential::Actor) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let req_body: RequestBody = api::read_json_body(request)?;
+    let token = req_body.token.unwrap_or_else(|| token::generate(&config.api.token_prefix));
+    let credential = credential::new(&req_body.credential, token::hash(&config.api.token_hash_key, token.as_str()));
+
+    let data_root = config.server.data_root.write().unwrap();
+    datastore::add_entry(&data_root.credentials(), &credential.id.to_string(), &credential)
+        .map_err(handle_datastore_error)?;
+
+    audit::record(&data_root, &audit::new(actor, "credential.create", &credential.id.to_string(), None, Some(&credential)));
+
+    api::prepare_json_response(&Response{
+        credential: credential::to_public(&credential),
+        token: &token,
+    })
+}
+
+fn handle_datastore_error(err: datastore::AddError) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 500,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "datastore".to_string(),
+            message: err.to_string(),
+        }
+    }
+}
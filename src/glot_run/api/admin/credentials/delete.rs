@@ -0,0 +1,31 @@
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::credential;
+use crate::glot_run::datastore;
+use crate::glot_run::audit;
+
+
+
+pub fn handle(config: &config::Config, actor: &credential::Actor, credential_id: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let data_root = config.server.data_root.write().unwrap();
+    let old_credential = datastore::get_entry::<credential::Credential>(&data_root.credentials(), credential_id).ok();
+    datastore::remove_entry(&data_root.credentials(), credential_id)
+        .map_err(handle_datastore_error)?;
+
+    if old_credential.is_some() {
+        audit::record(&data_root, &audit::new(actor, "credential.delete", credential_id, old_credential.as_ref(), None));
+    }
+
+    Ok(api::prepare_empty_response())
+}
+
+fn handle_datastore_error(err: datastore::AddError) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 500,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "datastore".to_string(),
+            message: err.to_string(),
+        }
+    }
+}
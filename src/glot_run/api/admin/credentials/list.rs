@@ -0,0 +1,32 @@
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::credential;
+use crate::glot_run::datastore;
+use crate::glot_run::storage;
+
+
+
+pub fn handle(config: &config::Config) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let data_root = config.server.data_root.read().unwrap();
+    let mut credentials = datastore::list_values::<credential::Credential>(&data_root.credentials())
+        .map_err(handle_datastore_error)?;
+
+    credentials.sort_by_key(|credential| credential.name.clone());
+
+    let credentials = credentials.iter()
+        .map(credential::to_public)
+        .collect::<Vec<credential::PublicCredential>>();
+
+    api::prepare_json_response(&credentials)
+}
+
+fn handle_datastore_error(err: storage::Error) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 500,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "datastore".to_string(),
+            message: err.to_string(),
+        }
+    }
+}
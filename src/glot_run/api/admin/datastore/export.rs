@@ -0,0 +1,43 @@
+use std::collections::BTreeMap;
+
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::user;
+use crate::glot_run::language;
+use crate::glot_run::datastore;
+use crate::glot_run::export;
+use crate::glot_run::storage;
+
+
+
+pub fn handle(config: &config::Config) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let data_root = config.server.data_root.read().unwrap();
+    let users_table = data_root.users();
+    let languages_table = data_root.languages();
+
+    let mut schema_versions = BTreeMap::new();
+    schema_versions.insert(users_table.name().to_string(), datastore::schema_version(&users_table).map_err(handle_datastore_error)?);
+    schema_versions.insert(languages_table.name().to_string(), datastore::schema_version(&languages_table).map_err(handle_datastore_error)?);
+
+    let users = datastore::list_values::<user::User>(&users_table)
+        .map_err(handle_datastore_error)?;
+
+    let languages = datastore::list_values::<language::Language>(&languages_table)
+        .map_err(handle_datastore_error)?;
+
+    // Unlock datastore
+    drop(data_root);
+
+    api::prepare_json_response(&export::new(users, languages, schema_versions, &config.api.token_hash_key))
+}
+
+fn handle_datastore_error(err: storage::Error) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 500,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "datastore".to_string(),
+            message: err.to_string(),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::credential;
+use crate::glot_run::user;
+use crate::glot_run::language;
+use crate::glot_run::datastore;
+use crate::glot_run::audit;
+use crate::glot_run::export;
+use crate::glot_run::storage;
+use crate::glot_run::token;
+use crate::glot_run::util;
+
+
+
+pub fn handle(config: &config::Config, request: &mut tiny_http::Request, actor: &credential::Actor) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let mode = get_mode(request)?;
+    let document: export::Document = api::read_json_body(request)?;
+
+    util::err_if_false(document.format_version == export::FORMAT_VERSION, api::ErrorResponse{
+        status_code: 400,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "request.formatVersion".to_string(),
+            message: format!("Unsupported format version {}, expected {}", document.format_version, export::FORMAT_VERSION),
+        }
+    })?;
+
+    util::err_if_false(document.token_hash_key_fingerprint == token::key_fingerprint(&config.api.token_hash_key), api::ErrorResponse{
+        status_code: 400,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "request.tokenHashKeyFingerprint".to_string(),
+            message: "The document was exported with a different API_TOKEN_HASH_KEY, the imported tokens would not work".to_string(),
+        }
+    })?;
+
+    let data_root = config.server.data_root.write().unwrap();
+    let users_table = data_root.users();
+    let languages_table = data_root.languages();
+
+    check_schema_version(&document, &users_table)?;
+    check_schema_version(&document, &languages_table)?;
+
+    let existing_users = datastore::list_values::<user::User>(&users_table)
+        .map_err(handle_datastore_error)?;
+
+    let existing_languages = datastore::list_values::<language::Language>(&languages_table)
+        .map_err(handle_datastore_error)?;
+
+    let mut conflicts = Vec::new();
+    let users = export::import_users(&existing_users, &document.users, mode, &mut conflicts);
+    let languages = export::import_languages(&existing_languages, &document.languages, mode, &mut conflicts);
+
+    // Each table is written atomically, but not both tables together
+    write_entries(&users_table, &users.entries, mode)
+        .map_err(|err| handle_write_error(err, &[]))?;
+
+    write_entries(&languages_table, &languages.entries, mode)
+        .map_err(|err| handle_write_error(err, &[users_table.name()]))?;
+
+    let report = export::ImportReport{
+        users: users.report,
+        languages: languages.report,
+        conflicts,
+    };
+
+    audit::record(&data_root, &audit::new(actor, "datastore.import", mode.as_str(), None, Some(&report)));
+
+    api::prepare_json_response(&report)
+}
+
+fn write_entries<E: serde::Serialize>(table: &datastore::Table, entries: &[(String, E)], mode: export::ImportMode) -> Result<(), datastore::AddError> {
+    match mode {
+        export::ImportMode::Merge => {
+            datastore::add_entries(table, entries)
+        }
+
+        export::ImportMode::Replace => {
+            datastore::replace_entries(table, entries)
+        }
+    }
+}
+
+// Supported query params: mode (merge or replace), defaults to merge
+fn get_mode(request: &tiny_http::Request) -> Result<export::ImportMode, api::ErrorResponse> {
+    let mode = api::get_query_params(request)
+        .into_iter()
+        .find(|(key, _)| key == "mode")
+        .map(|(_, value)| value);
+
+    match mode {
+        Some(mode) => {
+            mode.parse().map_err(|err: export::ParseImportModeError| api::ErrorResponse{
+                status_code: 400,
+                headers: vec![],
+                body: api::ErrorBody{
+                    error: "request.mode".to_string(),
+                    message: err.to_string(),
+                }
+            })
+        }
+
+        None => {
+            Ok(export::ImportMode::Merge)
+        }
+    }
+}
+
+// Entries are imported as is, so the document must have the same schema version as the datastore
+fn check_schema_version(document: &export::Document, table: &datastore::Table) -> Result<(), api::ErrorResponse> {
+    let version = datastore::schema_version(table)
+        .map_err(handle_datastore_error)?;
+
+    let document_version = document.schema_versions.get(table.name());
+
+    util::err_if_false(document_version == Some(&version), api::ErrorResponse{
+        status_code: 400,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "request.schemaVersions".to_string(),
+            message: format!("Schema version of {} does not match the datastore version {}", table.name(), version),
+        }
+    })
+}
+
+fn handle_datastore_error(err: storage::Error) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 500,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "datastore".to_string(),
+            message: err.to_string(),
+        }
+    }
+}
+
+// The message tells which tables were imported before the failure
+fn handle_write_error(err: datastore::AddError, written_tables: &[&str]) -> api::ErrorResponse {
+    let message = if written_tables.is_empty() {
+        format!("Nothing was imported: {}", err)
+    } else {
+        format!("Only the {} table was imported: {}", written_tables.join(", "), err)
+    };
+
+    api::ErrorResponse{
+        status_code: 500,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "datastore".to_string(),
+            message,
+        }
+    }
+}
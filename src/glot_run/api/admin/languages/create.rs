@@ -1,29 +1,31 @@
 use crate::glot_run::config;
 use crate::glot_run::api;
+use crate::glot_run::credential;
 use crate::glot_run::language;
 use crate::glot_run::datastore;
+use crate::glot_run::api::admin::languages;
 
 
 
-pub fn handle(config: &config::Config, request: &mut tiny_http::Request) -> Result<api::SuccessResponse, api::ErrorResponse> {
-    api::check_access_token(&config.api.admin_access_token, request)?;
-
+pub fn handle(config: &config::Config, request: &mut tiny_http::Request, actor: &credential::Actor) -> Result<api::SuccessResponse, api::ErrorResponse> {
     let language_data: language::LanguageData = api::read_json_body(request)?;
     let language = language::new(&language_data);
 
-    let data_root = config.server.data_root.lock().unwrap();
-    datastore::add_entry(&data_root.languages_path(), &language.id, &language)
-        .map_err(handle_datastore_error)?;
-
-    api::prepare_json_response(&language)
-}
+    let data_root = config.server.data_root.write().unwrap();
+    let old_language = datastore::get_entry::<language::Language>(&data_root.languages(), &language.id).ok();
 
-fn handle_datastore_error(err: datastore::AddError) -> api::ErrorResponse {
-    api::ErrorResponse{
-        status_code: 500,
-        body: api::ErrorBody{
-            error: "datastore".to_string(),
-            message: err.to_string(),
-        }
+    if let Some(old_language) = &old_language {
+        api::check_if_match(request, old_language.revision)?;
     }
+
+    let language = match &old_language {
+        Some(old_language) => language::replace(old_language, &language_data),
+        None => language,
+    };
+
+    let action = if old_language.is_some() { "language.update" } else { "language.create" };
+    languages::save(&data_root, actor, action, old_language.as_ref(), &language)?;
+
+    api::prepare_json_response(&language)
+        .map(|response| api::add_header(response, api::header("ETag", &api::etag(language.revision))))
 }
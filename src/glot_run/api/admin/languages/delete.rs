@@ -1,18 +1,28 @@
 use crate::glot_run::config;
 use crate::glot_run::api;
+use crate::glot_run::credential;
 use crate::glot_run::language;
 use crate::glot_run::datastore;
+use crate::glot_run::audit;
 
 
 
 
-pub fn handle(config: &config::Config, request: &mut tiny_http::Request, language_id: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
-    api::check_access_token(&config.api.admin_access_token, request)?;
+pub fn handle(config: &config::Config, request: &mut tiny_http::Request, actor: &credential::Actor, language_id: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let data_root = config.server.data_root.write().unwrap();
+    let old_language = datastore::get_entry::<language::Language>(&data_root.languages(), language_id).ok();
 
-    let data_root = config.server.data_root.lock().unwrap();
-    datastore::remove_entry::<language::Language>(&data_root.languages_path(), language_id)
+    if let Some(old_language) = &old_language {
+        api::check_if_match(request, old_language.revision)?;
+    }
+
+    datastore::remove_entry(&data_root.languages(), language_id)
         .map_err(handle_datastore_error)?;
 
+    if old_language.is_some() {
+        audit::record(&data_root, &audit::new(actor, "language.delete", language_id, old_language.as_ref(), None));
+    }
+
     Ok(api::prepare_empty_response())
 }
 
@@ -20,6 +30,7 @@ pub fn handle(config: &config::Config, request: &mut tiny_http::Request, languag
 fn handle_datastore_error(err: datastore::AddError) -> api::ErrorResponse {
     api::ErrorResponse{
         status_code: 500,
+        headers: vec![],
         body: api::ErrorBody{
             error: "datastore".to_string(),
             message: err.to_string(),
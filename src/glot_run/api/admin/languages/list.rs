@@ -2,15 +2,13 @@ use crate::glot_run::config;
 use crate::glot_run::api;
 use crate::glot_run::language;
 use crate::glot_run::datastore;
-use crate::glot_run::file;
+use crate::glot_run::storage;
 
 
 
-pub fn handle(config: &config::Config, request: &mut tiny_http::Request) -> Result<api::SuccessResponse, api::ErrorResponse> {
-    api::check_access_token(&config.api.admin_access_token, request)?;
-
-    let data_root = config.server.data_root.lock().unwrap();
-    let mut languages = datastore::list_values::<language::Language>(&data_root.languages_path())
+pub fn handle(config: &config::Config) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let data_root = config.server.data_root.read().unwrap();
+    let mut languages = datastore::list_values::<language::Language>(&data_root.languages())
         .map_err(handle_datastore_error)?;
 
     languages.sort_by_key(|language| language.name.clone());
@@ -19,10 +17,11 @@ pub fn handle(config: &config::Config, request: &mut tiny_http::Request) -> Resu
 }
 
 
-fn handle_datastore_error(err: file::ReadJsonError) -> api::ErrorResponse {
+fn handle_datastore_error(err: storage::Error) -> api::ErrorResponse {
 
     api::ErrorResponse{
         status_code: 500,
+        headers: vec![],
         body: api::ErrorBody{
             error: "datastore".to_string(),
             message: err.to_string(),
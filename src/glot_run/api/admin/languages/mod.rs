@@ -2,3 +2,64 @@ pub mod create;
 pub mod delete;
 pub mod list;
 pub mod get;
+pub mod update;
+
+use std::iter;
+
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::credential;
+use crate::glot_run::language;
+use crate::glot_run::datastore;
+use crate::glot_run::audit;
+
+
+// Writes the language together with the other versions of the language that lose aliases to it, in one write
+pub fn save(data_root: &config::DataRoot, actor: &credential::Actor, action: &str, old_language: Option<&language::Language>, language: &language::Language) -> Result<(), api::ErrorResponse> {
+    let languages = datastore::list_values::<language::Language>(&data_root.languages())
+        .map_err(datastore::AddError::Read)
+        .map_err(handle_datastore_error)?;
+
+    language::validate_aliases(&languages, language)
+        .map_err(handle_alias_error)?;
+
+    let previous_owners = language::take_aliases(&languages, language);
+    let entries = iter::once(language)
+        .chain(previous_owners.iter())
+        .map(|language| (language.id.clone(), language.clone()))
+        .collect::<Vec<(String, language::Language)>>();
+
+    datastore::add_entries(&data_root.languages(), &entries)
+        .map_err(handle_datastore_error)?;
+
+    audit::record(data_root, &audit::new(actor, action, &language.id, old_language, Some(language)));
+
+    for other in &previous_owners {
+        let old_other = languages.iter().find(|language| language.id == other.id);
+        audit::record(data_root, &audit::new(actor, "language.update", &other.id, old_other, Some(other)));
+    }
+
+    Ok(())
+}
+
+fn handle_alias_error(err: language::AliasError) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 400,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "request.aliases".to_string(),
+            message: err.to_string(),
+        }
+    }
+}
+
+fn handle_datastore_error(err: datastore::AddError) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 500,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "datastore".to_string(),
+            message: err.to_string(),
+        }
+    }
+}
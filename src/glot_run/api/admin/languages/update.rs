@@ -0,0 +1,68 @@
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::credential;
+use crate::glot_run::language;
+use crate::glot_run::datastore;
+use crate::glot_run::util;
+use crate::glot_run::api::admin::languages;
+
+
+
+pub fn handle(config: &config::Config, request: &mut tiny_http::Request, actor: &credential::Actor, language_id: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let update_data: language::UpdateData = api::read_json_body(request)?;
+
+    if let Some(Some(sunset)) = &update_data.sunset {
+        util::parse_rfc3339(sunset)
+            .map_err(handle_sunset_error)?;
+    }
+
+    let data_root = config.server.data_root.write().unwrap();
+    let old_language = datastore::get_entry::<language::Language>(&data_root.languages(), language_id)
+        .map_err(handle_datastore_error)?;
+
+    api::check_if_match(request, old_language.revision)?;
+
+    let language = language::update(&old_language, &update_data);
+    languages::save(&data_root, actor, "language.update", Some(&old_language), &language)?;
+
+    api::prepare_json_response(&language)
+        .map(|response| api::add_header(response, api::header("ETag", &api::etag(language.revision))))
+}
+
+
+fn handle_sunset_error(err: chrono::ParseError) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 400,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "request.sunset".to_string(),
+            message: format!("Invalid rfc3339 timestamp: {}", err),
+        }
+    }
+}
+
+fn handle_datastore_error(err: datastore::GetError) -> api::ErrorResponse {
+    match err {
+        datastore::GetError::Read(_) => {
+            api::ErrorResponse{
+                status_code: 500,
+                headers: vec![],
+                body: api::ErrorBody{
+                    error: "datastore".to_string(),
+                    message: err.to_string(),
+                }
+            }
+        }
+
+        datastore::GetError::NotFound() => {
+            api::ErrorResponse{
+                status_code: 404,
+                headers: vec![],
+                body: api::ErrorBody{
+                    error: "not_found".to_string(),
+                    message: err.to_string(),
+                }
+            }
+        }
+    }
+}
@@ -1,2 +1,6 @@
 pub mod users;
 pub mod languages;
+pub mod credentials;
+pub mod audit;
+pub mod runs;
+pub mod datastore;
@@ -0,0 +1,49 @@
+use std::time;
+
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::run_log;
+use crate::glot_run::file;
+
+
+
+pub fn handle(config: &config::Config, run_id: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let now = time::SystemTime::now();
+    let mut found_entry = None;
+
+    run_log::for_each_entry(&config.api.run_log_file, |entry| {
+        if entry.id.to_string() == run_id && run_log::is_retained(&config.api.run_log, &entry, now) {
+            found_entry = Some(entry);
+            false
+        } else {
+            true
+        }
+    }).map_err(handle_read_error)?;
+
+    let entry = found_entry.ok_or_else(run_not_found_error)?;
+
+    api::prepare_json_response(&entry)
+}
+
+
+fn run_not_found_error() -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 404,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "not_found".to_string(),
+            message: "Run not found".to_string(),
+        }
+    }
+}
+
+fn handle_read_error(err: file::ReadJsonError) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 500,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "datastore".to_string(),
+            message: err.to_string(),
+        }
+    }
+}
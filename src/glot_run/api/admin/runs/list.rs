@@ -0,0 +1,118 @@
+use std::collections::VecDeque;
+use std::time;
+
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::run_log;
+use crate::glot_run::file;
+
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    runs: Vec<run_log::Entry>,
+    total: usize,
+    offset: usize,
+    limit: usize,
+}
+
+
+pub fn handle(config: &config::Config, request: &mut tiny_http::Request) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let query_params = api::get_query_params(request);
+    let offset = parse_number_param(&query_params, "offset")?.unwrap_or(0);
+    let limit = parse_number_param(&query_params, "limit")?.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+
+    let now = time::SystemTime::now();
+
+    // The log is oldest first, so only the newest offset + limit matching entries are kept while reading it
+    let window = offset.saturating_add(limit);
+    let mut newest = VecDeque::new();
+    let mut total = 0;
+
+    run_log::for_each_entry(&config.api.run_log_file, |entry| {
+        if run_log::is_retained(&config.api.run_log, &entry, now) && matches_query(&entry, &query_params) {
+            total += 1;
+
+            // Files and outputs are only included when getting a single run
+            newest.push_back(run_log::Entry{
+                details: None,
+                ..entry
+            });
+
+            if newest.len() > window {
+                newest.pop_front();
+            }
+        }
+
+        true
+    }).map_err(handle_read_error)?;
+
+    // Newest runs first
+    let runs = newest.into_iter()
+        .rev()
+        .skip(offset)
+        .collect();
+
+    api::prepare_json_response(&Response{
+        runs,
+        total,
+        offset,
+        limit,
+    })
+}
+
+// Supported query params: userId, language (name or name/version) and error (error class)
+fn matches_query(entry: &run_log::Entry, query_params: &[(String, String)]) -> bool {
+    query_params.iter().all(|(key, value)| {
+        match key.as_str() {
+            "userId" => {
+                entry.user_id.to_string() == *value
+            }
+
+            "language" => {
+                match value.split_once('/') {
+                    Some((name, version)) => entry.language == name && entry.version == version,
+                    None => entry.language == *value,
+                }
+            }
+
+            "error" => {
+                entry.error.as_ref() == Some(value)
+            }
+
+            _ => {
+                true
+            }
+        }
+    })
+}
+
+fn parse_number_param(query_params: &[(String, String)], name: &str) -> Result<Option<usize>, api::ErrorResponse> {
+    query_params.iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.parse::<usize>())
+        .transpose()
+        .map_err(|err| api::ErrorResponse{
+            status_code: 400,
+            headers: vec![],
+            body: api::ErrorBody{
+                error: format!("request.{}", name),
+                message: format!("Invalid number: {}", err),
+            }
+        })
+}
+
+fn handle_read_error(err: file::ReadJsonError) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 500,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "datastore".to_string(),
+            message: err.to_string(),
+        }
+    }
+}
@@ -1,33 +1,74 @@
 use crate::glot_run::config;
 use crate::glot_run::api;
+use crate::glot_run::credential;
 use crate::glot_run::user;
 use crate::glot_run::datastore;
-
+use crate::glot_run::audit;
+use crate::glot_run::token;
+use crate::glot_run::util;
+use crate::glot_run::api::admin::users;
 
 
 #[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct RequestBody {
-    token: ascii::AsciiString,
+    // A token is generated when it is not given
+    token: Option<ascii::AsciiString>,
+    token_expires: Option<String>,
+    #[serde(flatten)]
+    user: user::UserData,
 }
 
+// The plaintext token is only returned when the user is created
+#[derive(Debug, serde::Serialize)]
+struct Response<'a> {
+    #[serde(flatten)]
+    user: user::PublicUser<'a>,
+    token: &'a ascii::AsciiString,
+}
 
-pub fn handle(config: &config::Config, request: &mut tiny_http::Request) -> Result<api::SuccessResponse, api::ErrorResponse> {
-    api::check_access_token(&config.api.admin_access_token, request)?;
 
+pub fn handle(config: &config::Config, request: &mut tiny_http::Request, actor: &credential::Actor) -> Result<api::SuccessResponse, api::ErrorResponse> {
     let req_body: RequestBody = api::read_json_body(request)?;
-    let user = user::new(&req_body.token);
+    let token_expires = req_body.token_expires
+        .map(|expires| util::parse_rfc3339(&expires))
+        .transpose()
+        .map_err(handle_token_expires_error)?;
+
+    let token = req_body.token.unwrap_or_else(|| token::generate(&config.api.token_prefix));
+    let token_hash = token::hash(&config.api.token_hash_key, token.as_str());
+    let user = user::new(&req_body.user, user::new_token(user::DEFAULT_TOKEN_NAME, token_hash.clone(), token_expires));
 
-    let data_root = config.server.data_root.lock().unwrap();
-    datastore::add_entry(&data_root.users_path(), &user.id.to_string(), &user)
+    let data_root = config.server.data_root.write().unwrap();
+    users::check_token_unused(&data_root, &token_hash, None)?;
+
+    datastore::add_entry(&data_root.users(), &user.id.to_string(), &user)
         .map_err(handle_datastore_error)?;
 
-    api::prepare_json_response(&user)
+    audit::record(&data_root, &audit::new(actor, "user.create", &user.id.to_string(), None, Some(&user)));
+
+    api::prepare_json_response(&Response{
+        user: user::to_public(&user),
+        token: &token,
+    }).map(|response| api::add_header(response, api::header("ETag", &api::etag(user.revision))))
+}
+
+fn handle_token_expires_error(err: chrono::ParseError) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 400,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "request.token_expires".to_string(),
+            message: format!("Invalid rfc3339 timestamp: {}", err),
+        }
+    }
 }
 
 fn handle_datastore_error(err: datastore::AddError) -> api::ErrorResponse {
 
     api::ErrorResponse{
         status_code: 500,
+        headers: vec![],
         body: api::ErrorBody{
             error: "datastore".to_string(),
             message: err.to_string(),
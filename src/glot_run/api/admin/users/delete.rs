@@ -1,17 +1,34 @@
 use crate::glot_run::config;
 use crate::glot_run::api;
+use crate::glot_run::credential;
 use crate::glot_run::user;
 use crate::glot_run::datastore;
+use crate::glot_run::audit;
 
 
 
-pub fn handle(config: &config::Config, request: &mut tiny_http::Request, user_id: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
-    api::check_access_token(&config.api.admin_access_token, request)?;
+pub fn handle(config: &config::Config, request: &mut tiny_http::Request, actor: &credential::Actor, user_id: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let data_root = config.server.data_root.write().unwrap();
+    let old_user = datastore::get_entry::<user::User>(&data_root.users(), user_id).ok();
 
-    let data_root = config.server.data_root.lock().unwrap();
-    datastore::remove_entry::<user::User>(&data_root.users_path(), user_id)
+    if let Some(old_user) = &old_user {
+        api::check_if_match(request, old_user.revision)?;
+    }
+
+    datastore::remove_entry(&data_root.users(), user_id)
+        .map_err(handle_datastore_error)?;
+
+    datastore::remove_entry(&data_root.usage(), user_id)
         .map_err(handle_datastore_error)?;
 
+    if let Some(old_user) = &old_user {
+        config.api.pending_usage.take(&old_user.id);
+    }
+
+    if old_user.is_some() {
+        audit::record(&data_root, &audit::new(actor, "user.delete", user_id, old_user.as_ref(), None));
+    }
+
     Ok(api::prepare_empty_response())
 }
 
@@ -19,6 +36,7 @@ fn handle_datastore_error(err: datastore::AddError) -> api::ErrorResponse {
 
     api::ErrorResponse{
         status_code: 500,
+        headers: vec![],
         body: api::ErrorBody{
             error: "datastore".to_string(),
             message: err.to_string(),
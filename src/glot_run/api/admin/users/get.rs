@@ -5,14 +5,13 @@ use crate::glot_run::datastore;
 
 
 
-pub fn handle(config: &config::Config, request: &mut tiny_http::Request, user_id: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
-    api::check_access_token(&config.api.admin_access_token, request)?;
-
-    let data_root = config.server.data_root.lock().unwrap();
-    let user = datastore::get_entry::<user::User>(&data_root.users_path(), user_id).
+pub fn handle(config: &config::Config, user_id: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let data_root = config.server.data_root.read().unwrap();
+    let user = datastore::get_entry::<user::User>(&data_root.users(), user_id).
         map_err(handle_datastore_error)?;
 
-    api::prepare_json_response(&user)
+    api::prepare_json_response(&user::to_public(&user))
+        .map(|response| api::add_header(response, api::header("ETag", &api::etag(user.revision))))
 }
 
 
@@ -21,6 +20,7 @@ fn handle_datastore_error(err: datastore::GetError) -> api::ErrorResponse {
         datastore::GetError::Read(_) => {
             api::ErrorResponse{
                 status_code: 500,
+                headers: vec![],
                 body: api::ErrorBody{
                     error: "datastore".to_string(),
                     message: err.to_string(),
@@ -31,6 +31,7 @@ fn handle_datastore_error(err: datastore::GetError) -> api::ErrorResponse {
         datastore::GetError::NotFound() => {
             api::ErrorResponse{
                 status_code: 404,
+                headers: vec![],
                 body: api::ErrorBody{
                     error: "not_found".to_string(),
                     message: err.to_string(),
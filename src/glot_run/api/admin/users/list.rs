@@ -2,23 +2,52 @@ use crate::glot_run::config;
 use crate::glot_run::api;
 use crate::glot_run::user;
 use crate::glot_run::datastore;
-use crate::glot_run::file;
+use crate::glot_run::storage;
 
 
 
 pub fn handle(config: &config::Config, request: &mut tiny_http::Request) -> Result<api::SuccessResponse, api::ErrorResponse> {
-    api::check_access_token(&config.api.admin_access_token, request)?;
-
-    let data_root = config.server.data_root.lock().unwrap();
-    let users = datastore::list_values::<user::User>(&data_root.users_path())
+    let query_params = api::get_query_params(request);
+    let data_root = config.server.data_root.read().unwrap();
+    let users = datastore::list_values::<user::User>(&data_root.users())
         .map_err(handle_datastore_error)?;
 
+    let users = users.into_iter()
+        .filter(|user| matches_query(user, &query_params))
+        .collect::<Vec<user::User>>();
+
+    let users = users.iter()
+        .map(user::to_public)
+        .collect::<Vec<user::PublicUser>>();
+
     api::prepare_json_response(&users)
 }
 
-fn handle_datastore_error(err: file::ReadJsonError) -> api::ErrorResponse {
+// Supported query params: label (key:value or key, can be repeated) and name (case insensitive substring)
+fn matches_query(user: &user::User, query_params: &[(String, String)]) -> bool {
+    query_params.iter().all(|(key, value)| {
+        match key.as_str() {
+            "label" => {
+                user::has_label(user, value)
+            }
+
+            "name" => {
+                user.name.as_ref()
+                    .map(|name| name.to_lowercase().contains(&value.to_lowercase()))
+                    .unwrap_or(false)
+            }
+
+            _ => {
+                true
+            }
+        }
+    })
+}
+
+fn handle_datastore_error(err: storage::Error) -> api::ErrorResponse {
     api::ErrorResponse{
         status_code: 500,
+        headers: vec![],
         body: api::ErrorBody{
             error: "datastore".to_string(),
             message: err.to_string(),
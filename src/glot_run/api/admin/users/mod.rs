@@ -3,3 +3,43 @@ pub mod delete;
 pub mod list;
 pub mod get;
 pub mod update;
+pub mod usage;
+pub mod tokens;
+
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::user;
+use crate::glot_run::datastore;
+
+
+// Token hashes must be unique across users since a token authenticates the user that has its hash.
+// The caller must hold the datastore write lock until the token is written
+pub fn check_token_unused(data_root: &config::DataRoot, token_hash: &str, user_id: Option<&uuid::Uuid>) -> Result<(), api::ErrorResponse> {
+    match datastore::find_by_index::<user::User>(&data_root.users(), &user::TOKEN_HASH_INDEX, token_hash) {
+        Ok(owner) if Some(&owner.id) != user_id => {
+            Err(api::ErrorResponse{
+                status_code: 409,
+                headers: vec![],
+                body: api::ErrorBody{
+                    error: "token_exists".to_string(),
+                    message: "The token is already used by another user".to_string(),
+                }
+            })
+        }
+
+        Ok(_) | Err(datastore::GetError::NotFound()) => {
+            Ok(())
+        }
+
+        Err(err) => {
+            Err(api::ErrorResponse{
+                status_code: 500,
+                headers: vec![],
+                body: api::ErrorBody{
+                    error: "datastore".to_string(),
+                    message: err.to_string(),
+                }
+            })
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::credential;
+use crate::glot_run::user;
+use crate::glot_run::datastore;
+use crate::glot_run::audit;
+use crate::glot_run::token;
+use crate::glot_run::util;
+use crate::glot_run::api::admin::users;
+
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestBody {
+    name: String,
+    // A token is generated when it is not given
+    token: Option<ascii::AsciiString>,
+    expires: Option<String>,
+}
+
+// The plaintext token is only returned when the token is created
+#[derive(Debug, serde::Serialize)]
+struct Response<'a> {
+    #[serde(flatten)]
+    token: user::PublicToken<'a>,
+    #[serde(rename = "token")]
+    plaintext_token: &'a ascii::AsciiString,
+}
+
+
+pub fn handle(config: &config::Config, request: &mut tiny_http::Request, actor: &credential::Actor, user_id: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let req_body: RequestBody = api::read_json_body(request)?;
+    let expires = req_body.expires
+        .map(|expires| util::parse_rfc3339(&expires))
+        .transpose()
+        .map_err(handle_expires_error)?;
+
+    let plaintext_token = req_body.token.unwrap_or_else(|| token::generate(&config.api.token_prefix));
+    let token_hash = token::hash(&config.api.token_hash_key, plaintext_token.as_str());
+    let new_token = user::new_token(&req_body.name, token_hash, expires);
+
+    let data_root = config.server.data_root.write().unwrap();
+    let old_user = datastore::get_entry::<user::User>(&data_root.users(), user_id).ok();
+
+    users::check_token_unused(&data_root, &new_token.hash, old_user.as_ref().map(|user| &user.id))?;
+
+    let user = datastore::update_entry::<_, user::User>(&data_root.users(), user_id, |user| {
+        user::add_token(user, new_token.clone())
+    }).map_err(handle_datastore_error)?;
+
+    audit::record(&data_root, &audit::new(actor, "user.token.create", user_id, old_user.as_ref(), Some(&user)));
+
+    api::prepare_json_response(&Response{
+        token: user::to_public_token(&new_token),
+        plaintext_token: &plaintext_token,
+    })
+}
+
+
+fn handle_expires_error(err: chrono::ParseError) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 400,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "request.expires".to_string(),
+            message: format!("Invalid rfc3339 timestamp: {}", err),
+        }
+    }
+}
+
+fn handle_datastore_error(err: datastore::UpdateError) -> api::ErrorResponse {
+    match err {
+        datastore::UpdateError::NotFound() => {
+            api::ErrorResponse{
+                status_code: 404,
+                headers: vec![],
+                body: api::ErrorBody{
+                    error: "not_found".to_string(),
+                    message: err.to_string(),
+                }
+            }
+        }
+
+        _ => {
+            api::ErrorResponse{
+                status_code: 500,
+                headers: vec![],
+                body: api::ErrorBody{
+                    error: "datastore".to_string(),
+                    message: err.to_string(),
+                }
+            }
+        }
+    }
+}
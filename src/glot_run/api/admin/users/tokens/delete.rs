@@ -0,0 +1,78 @@
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::credential;
+use crate::glot_run::user;
+use crate::glot_run::datastore;
+use crate::glot_run::audit;
+use crate::glot_run::util;
+
+
+
+pub fn handle(config: &config::Config, actor: &credential::Actor, user_id: &str, token_id: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let token_id = uuid::Uuid::parse_str(token_id)
+        .map_err(|_| token_not_found_error())?;
+
+    let data_root = config.server.data_root.write().unwrap();
+    let user = datastore::get_entry::<user::User>(&data_root.users(), user_id)
+        .map_err(handle_datastore_error)?;
+
+    let token_exists = user.tokens.iter().any(|token| token.id == token_id);
+    util::err_if_false(token_exists, token_not_found_error())?;
+
+    let new_user = datastore::update_entry::<_, user::User>(&data_root.users(), user_id, |user| {
+        user::remove_token(user, &token_id)
+    }).map_err(handle_update_error)?;
+
+    audit::record(&data_root, &audit::new(actor, "user.token.delete", user_id, Some(&user), Some(&new_user)));
+
+    Ok(api::prepare_empty_response())
+}
+
+
+fn token_not_found_error() -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 404,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "not_found".to_string(),
+            message: "Token not found".to_string(),
+        }
+    }
+}
+
+fn handle_datastore_error(err: datastore::GetError) -> api::ErrorResponse {
+    match err {
+        datastore::GetError::Read(_) => {
+            api::ErrorResponse{
+                status_code: 500,
+                headers: vec![],
+                body: api::ErrorBody{
+                    error: "datastore".to_string(),
+                    message: err.to_string(),
+                }
+            }
+        }
+
+        datastore::GetError::NotFound() => {
+            api::ErrorResponse{
+                status_code: 404,
+                headers: vec![],
+                body: api::ErrorBody{
+                    error: "not_found".to_string(),
+                    message: err.to_string(),
+                }
+            }
+        }
+    }
+}
+
+fn handle_update_error(err: datastore::UpdateError) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 500,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "datastore".to_string(),
+            message: err.to_string(),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::user;
+use crate::glot_run::datastore;
+
+
+
+pub fn handle(config: &config::Config, user_id: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let data_root = config.server.data_root.read().unwrap();
+    let user = datastore::get_entry::<user::User>(&data_root.users(), user_id)
+        .map_err(handle_datastore_error)?;
+
+    let tokens = user.tokens.iter()
+        .map(user::to_public_token)
+        .collect::<Vec<user::PublicToken>>();
+
+    api::prepare_json_response(&tokens)
+}
+
+
+fn handle_datastore_error(err: datastore::GetError) -> api::ErrorResponse {
+    match err {
+        datastore::GetError::Read(_) => {
+            api::ErrorResponse{
+                status_code: 500,
+                headers: vec![],
+                body: api::ErrorBody{
+                    error: "datastore".to_string(),
+                    message: err.to_string(),
+                }
+            }
+        }
+
+        datastore::GetError::NotFound() => {
+            api::ErrorResponse{
+                status_code: 404,
+                headers: vec![],
+                body: api::ErrorBody{
+                    error: "not_found".to_string(),
+                    message: err.to_string(),
+                }
+            }
+        }
+    }
+}
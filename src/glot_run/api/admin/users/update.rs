@@ -1,32 +1,133 @@
+use std::time;
+
 use crate::glot_run::config;
 use crate::glot_run::api;
+use crate::glot_run::credential;
 use crate::glot_run::user;
+use crate::glot_run::usage;
 use crate::glot_run::datastore;
+use crate::glot_run::audit;
+use crate::glot_run::token;
+use crate::glot_run::util;
+use crate::glot_run::api::admin::users;
 
 
 #[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct RequestBody {
-    token: ascii::AsciiString,
+    // Rotates to the given token, or a generated token if rotate_token is set
+    token: Option<ascii::AsciiString>,
+    #[serde(default)]
+    rotate_token: bool,
+    token_name: Option<String>,
+    token_expires: Option<String>,
+    token_grace_period: Option<u64>,
+    #[serde(flatten)]
+    user: user::UpdateData,
+    #[serde(default)]
+    reset_quota_usage: bool,
 }
 
-pub fn handle(config: &config::Config, request: &mut tiny_http::Request, user_id: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
-    api::check_access_token(&config.api.admin_access_token, request)?;
+// The plaintext token is only returned when it was generated by the server
+#[derive(Debug, serde::Serialize)]
+struct Response<'a> {
+    #[serde(flatten)]
+    user: user::PublicUser<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<&'a ascii::AsciiString>,
+}
 
+pub fn handle(config: &config::Config, request: &mut tiny_http::Request, actor: &credential::Actor, user_id: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
     let req_body: RequestBody = api::read_json_body(request)?;
-    let data_root = config.server.data_root.lock().unwrap();
-    let user = datastore::update_entry::<_, user::User>(&data_root.users_path(), user_id, |user| {
-        user::update_token(user, &req_body.token)
+    let token_expires = req_body.token_expires.as_ref()
+        .map(|expires| util::parse_rfc3339(expires))
+        .transpose()
+        .map_err(handle_token_expires_error)?;
+
+    let generated_token = if req_body.token.is_none() && req_body.rotate_token {
+        Some(token::generate(&config.api.token_prefix))
+    } else {
+        None
+    };
+
+    let new_token = req_body.token.as_ref().or(generated_token.as_ref()).map(|token| {
+        let token_hash = token::hash(&config.api.token_hash_key, token.as_str());
+        let token_name = req_body.token_name.as_deref().unwrap_or(user::DEFAULT_TOKEN_NAME);
+        user::new_token(token_name, token_hash, token_expires)
+    });
+
+    let grace_period = req_body.token_grace_period
+        .map(time::Duration::from_secs)
+        .unwrap_or(config.api.token_rotation_grace_period);
+
+    let data_root = config.server.data_root.write().unwrap();
+    let old_user = datastore::get_entry::<user::User>(&data_root.users(), user_id).ok();
+
+    if let Some(old_user) = &old_user {
+        api::check_if_match(request, old_user.revision)?;
+    }
+
+    if let Some(token) = &new_token {
+        users::check_token_unused(&data_root, &token.hash, old_user.as_ref().map(|user| &user.id))?;
+    }
+
+    let user = datastore::update_entry::<_, user::User>(&data_root.users(), user_id, |user| {
+        let user = user::update(user, &req_body.user);
+
+        match new_token {
+            Some(token) => user::rotate_token(&user, token, grace_period),
+            None => user,
+        }
     }).map_err(handle_datastore_error)?;
 
-    api::prepare_json_response(&user)
+    if req_body.reset_quota_usage {
+        // Pending runs are written with the reset so the next flush doesn't add them to the new quota usage
+        let pending_runs = config.api.pending_usage.take(&user.id);
+
+        datastore::upsert_entry(&data_root.usage(), user_id, |entry: Option<&usage::Usage>| {
+            let user_usage = entry.cloned().unwrap_or_else(|| usage::new(&user.id));
+            usage::reset_quota_usage(&usage::add_runs(&user_usage, &pending_runs, config.api.usage_retention))
+        }).map_err(handle_usage_error)?;
+    }
+
+    audit::record(&data_root, &audit::new(actor, "user.update", user_id, old_user.as_ref(), Some(&user)));
+
+    api::prepare_json_response(&Response{
+        user: user::to_public(&user),
+        token: generated_token.as_ref(),
+    }).map(|response| api::add_header(response, api::header("ETag", &api::etag(user.revision))))
 }
 
 
+fn handle_token_expires_error(err: chrono::ParseError) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 400,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "request.token_expires".to_string(),
+            message: format!("Invalid rfc3339 timestamp: {}", err),
+        }
+    }
+}
+
+
+fn handle_usage_error(err: datastore::AddError) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 500,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "datastore".to_string(),
+            message: err.to_string(),
+        }
+    }
+}
+
 fn handle_datastore_error(err: datastore::UpdateError) -> api::ErrorResponse {
     match err {
         datastore::UpdateError::NotFound() => {
             api::ErrorResponse{
                 status_code: 404,
+                headers: vec![],
                 body: api::ErrorBody{
                     error: "not_found".to_string(),
                     message: err.to_string(),
@@ -37,6 +138,7 @@ fn handle_datastore_error(err: datastore::UpdateError) -> api::ErrorResponse {
         _ => {
             api::ErrorResponse{
                 status_code: 500,
+                headers: vec![],
                 body: api::ErrorBody{
                     error: "datastore".to_string(),
                     message: err.to_string(),
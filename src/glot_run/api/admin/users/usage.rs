@@ -0,0 +1,54 @@
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::user;
+use crate::glot_run::usage;
+use crate::glot_run::datastore;
+
+
+
+pub fn handle(config: &config::Config, user_id: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    let data_root = config.server.data_root.read().unwrap();
+    let user = datastore::get_entry::<user::User>(&data_root.users(), user_id)
+        .map_err(handle_datastore_error)?;
+
+    let user_usage = match datastore::get_entry::<usage::Usage>(&data_root.usage(), user_id) {
+        Err(datastore::GetError::NotFound()) => {
+            Ok(usage::new(&user.id))
+        }
+
+        result => {
+            result
+        }
+    }.map_err(handle_datastore_error)?;
+
+    let user_usage = config.api.pending_usage.apply(&user_usage, config.api.usage_retention);
+
+    api::prepare_json_response(&user_usage)
+}
+
+
+fn handle_datastore_error(err: datastore::GetError) -> api::ErrorResponse {
+    match err {
+        datastore::GetError::Read(_) => {
+            api::ErrorResponse{
+                status_code: 500,
+                headers: vec![],
+                body: api::ErrorBody{
+                    error: "datastore".to_string(),
+                    message: err.to_string(),
+                }
+            }
+        }
+
+        datastore::GetError::NotFound() => {
+            api::ErrorResponse{
+                status_code: 404,
+                headers: vec![],
+                body: api::ErrorBody{
+                    error: "not_found".to_string(),
+                    message: err.to_string(),
+                }
+            }
+        }
+    }
+}
@@ -2,7 +2,7 @@ use crate::glot_run::config;
 use crate::glot_run::api;
 use crate::glot_run::language;
 use crate::glot_run::datastore;
-use crate::glot_run::file;
+use crate::glot_run::storage;
 
 
 #[derive(Debug, Eq, PartialEq, serde::Serialize)]
@@ -14,8 +14,8 @@ pub struct Image {
 
 pub fn handle(config: &config::Config, _: &mut tiny_http::Request) -> Result<api::SuccessResponse, api::ErrorResponse> {
 
-    let data_root = config.server.data_root.lock().unwrap();
-    let mut images = datastore::list_values::<language::Language>(&data_root.languages_path()).map(|languages| {
+    let data_root = config.server.data_root.read().unwrap();
+    let mut images = datastore::list_values::<language::Language>(&data_root.languages()).map(|languages| {
         languages
             .iter()
             .map(to_image)
@@ -36,9 +36,10 @@ fn to_image(language: &language::Language) -> Image {
     }
 }
 
-fn handle_datastore_error(err: file::ReadJsonError) -> api::ErrorResponse{
+fn handle_datastore_error(err: storage::Error) -> api::ErrorResponse{
     api::ErrorResponse{
         status_code: 500,
+        headers: vec![],
         body: api::ErrorBody{
             error: "datastore".to_string(),
             message: err.to_string(),
@@ -2,7 +2,9 @@ use crate::glot_run::config;
 use crate::glot_run::api;
 use crate::glot_run::language;
 use crate::glot_run::datastore;
-use crate::glot_run::file;
+use crate::glot_run::storage;
+use crate::glot_run::user;
+use crate::glot_run::api::user_auth;
 
 
 #[derive(Debug, Eq, PartialEq, serde::Serialize)]
@@ -12,12 +14,14 @@ pub struct Language {
 }
 
 
-pub fn handle(config: &config::Config, _: &mut tiny_http::Request) -> Result<api::SuccessResponse, api::ErrorResponse> {
+pub fn handle(config: &config::Config, request: &mut tiny_http::Request) -> Result<api::SuccessResponse, api::ErrorResponse> {
 
-    let data_root = config.server.data_root.lock().unwrap();
-    let mut languages = datastore::list_values::<language::Language>(&data_root.languages_path()).map(|languages| {
+    let data_root = config.server.data_root.read().unwrap();
+    let user = user_auth::check_optional_user(&config.api, request, &data_root)?;
+    let mut languages = datastore::list_values::<language::Language>(&data_root.languages()).map(|languages| {
         languages
             .iter()
+            .filter(|language| user.as_ref().map(|user| user::can_run(user, language)).unwrap_or(true))
             .map(|language| to_language(config, language))
             .collect::<Vec<Language>>()
     }).map_err(handle_datastore_error)?;
@@ -36,9 +40,10 @@ fn to_language(config: &config::Config, language: &language::Language) -> Langua
     }
 }
 
-fn handle_datastore_error(err: file::ReadJsonError) -> api::ErrorResponse{
+fn handle_datastore_error(err: storage::Error) -> api::ErrorResponse{
     api::ErrorResponse{
         status_code: 500,
+        headers: vec![],
         body: api::ErrorBody{
             error: "datastore".to_string(),
             message: err.to_string(),
@@ -2,24 +2,33 @@ use crate::glot_run::config;
 use crate::glot_run::api;
 use crate::glot_run::language;
 use crate::glot_run::datastore;
-use crate::glot_run::file;
+use crate::glot_run::storage;
 use crate::glot_run::util;
+use crate::glot_run::user;
+use crate::glot_run::api::user_auth;
 
 
 #[derive(Debug, Eq, PartialEq, serde::Serialize)]
 pub struct Language {
     version: String,
     url: String,
+    aliases: Vec<String>,
+    state: language::State,
+    sunset: Option<String>,
+    #[serde(flatten)]
+    metadata: language::Metadata,
 }
 
 
-pub fn handle(config: &config::Config, _: &mut tiny_http::Request, language_name: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
+pub fn handle(config: &config::Config, request: &mut tiny_http::Request, language_name: &str) -> Result<api::SuccessResponse, api::ErrorResponse> {
 
-    let data_root = config.server.data_root.lock().unwrap();
-    let mut languages = datastore::list_values::<language::Language>(&data_root.languages_path()).map(|languages| {
+    let data_root = config.server.data_root.read().unwrap();
+    let user = user_auth::check_optional_user(&config.api, request, &data_root)?;
+    let mut languages = datastore::list_values::<language::Language>(&data_root.languages()).map(|languages| {
         languages
             .iter()
             .filter(|language| language.name == language_name)
+            .filter(|language| user.as_ref().map(|user| user::can_run(user, language)).unwrap_or(true))
             .map(|language| to_language(config, language))
             .collect::<Vec<Language>>()
     }).map_err(handle_datastore_error)?;
@@ -28,6 +37,7 @@ pub fn handle(config: &config::Config, _: &mut tiny_http::Request, language_name
 
     util::err_if_false(!languages.is_empty(), api::ErrorResponse{
         status_code: 404,
+        headers: vec![],
         body: api::ErrorBody{
             error: "not_found".to_string(),
             message: "Language not found".to_string(),
@@ -43,13 +53,18 @@ fn to_language(config: &config::Config, language: &language::Language) -> Langua
     Language{
         version: language.version.clone(),
         url: format!("{}/languages/{}/{}", config.server.base_url, language.name, language.version),
+        aliases: language.aliases.clone(),
+        state: language.state,
+        sunset: language.sunset.clone(),
+        metadata: language.metadata.clone(),
     }
 }
 
 
-fn handle_datastore_error(err: file::ReadJsonError) -> api::ErrorResponse{
+fn handle_datastore_error(err: storage::Error) -> api::ErrorResponse{
     api::ErrorResponse{
         status_code: 500,
+        headers: vec![],
         body: api::ErrorBody{
             error: "datastore".to_string(),
             message: err.to_string(),
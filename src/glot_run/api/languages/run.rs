@@ -1,9 +1,17 @@
+use std::time;
+
 use crate::glot_run::config;
 use crate::glot_run::api;
 use crate::glot_run::language;
 use crate::glot_run::user;
 use crate::glot_run::datastore;
 use crate::glot_run::run;
+use crate::glot_run::concurrency_limit;
+use crate::glot_run::usage;
+use crate::glot_run::run_log;
+use crate::glot_run::quota;
+use crate::glot_run::util;
+use crate::glot_run::api::user_auth;
 
 
 #[derive(Debug)]
@@ -21,29 +29,151 @@ pub struct RequestBody {
 
 
 pub fn handle(config: &config::Config, request: &mut tiny_http::Request, options: Options) -> Result<api::SuccessResponse, api::ErrorResponse> {
-    let data_root = config.server.data_root.lock().unwrap();
-    check_user(request, &data_root)?;
+    let data_root = config.server.data_root.read().unwrap();
+    let (user, token_id) = user_auth::check_user(&config.api, request, &data_root)?;
 
-    let language = datastore::find_value::<_, language::Language>(&data_root.languages_path(), |language| {
-        language.name == options.language && language.version == options.version
-    }).map_err(handle_datastore_error)?;
+    // Unlock datastore, recording the token use might need write access
+    drop(data_root);
+    record_token_use(config, &user, &token_id);
 
-    // Unlock mutex
+    let data_root = config.server.data_root.read().unwrap();
+
+    if let Some(user_quota) = &user.quota {
+        check_quota(config, &data_root, &user, user_quota)?;
+    }
+
+    let language = find_language(&data_root, &options)
+        .map_err(handle_datastore_error)?;
+
+    util::err_if_false(user::can_run(&user, &language), api::ErrorResponse{
+        status_code: 403,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "language_not_allowed".to_string(),
+            message: "User is not allowed to run this language".to_string(),
+        }
+    })?;
+
+    util::err_if_false(language.state != language::State::Disabled, api::ErrorResponse{
+        status_code: 403,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "language_disabled".to_string(),
+            message: format!("{} {} is disabled", language.name, language.version),
+        }
+    })?;
+
+    // Unlock datastore
     drop(data_root);
 
+    let mut rate_limiter = config.api.rate_limiter.lock().unwrap();
+    rate_limiter.acquire(&user.id, user.rate_limit)
+        .map_err(handle_rate_limit_error)?;
+
+    // Unlock mutex
+    drop(rate_limiter);
+
+    // The run slot is held until the permit goes out of scope
+    let _permit = concurrency_limit::ConcurrencyLimiter::acquire(&config.api.concurrency_limiter, &user.id, user.max_concurrent_runs)
+        .map_err(handle_concurrency_limit_error)?;
+
     let req_body: RequestBody = api::read_json_body(request)?;
+    let input_bytes = usage::input_bytes(&req_body.files);
 
-    let run_result = run::run(&config.run, run::RunRequest{
-        image: language.image,
+    let run_request = run::RunRequest{
+        image: language.image.clone(),
         payload: run::RunRequestPayload{
-            language: language.name,
+            language: language.name.clone(),
             files: req_body.files,
             stdin: req_body.stdin,
             command: req_body.command,
         }
-    }).map_err(handle_run_error)?;
+    };
+
+    let started_at = time::SystemTime::now();
+    let started = time::Instant::now();
+    let result = run::run(&config.run, &run_request);
+
+    let counters = usage::run_counters(input_bytes, result.as_ref().ok(), started.elapsed());
+    record_usage(config, &user, &counters);
+
+    let result = result.map_err(handle_run_error);
+    record_run(config, &user, &language, run_request, started_at, &counters, &result);
+
+    let run_result = result?;
 
     api::prepare_json_response(&run_result)
+        .map(|response| add_deprecation_headers(response, &language))
+}
+
+fn add_deprecation_headers(response: api::SuccessResponse, language: &language::Language) -> api::SuccessResponse {
+    if language.state != language::State::Deprecated {
+        return response
+    }
+
+    let response = api::add_header(response, api::header("Deprecation", "true"));
+
+    // The sunset is validated when it is set
+    match language.sunset.as_ref().and_then(|sunset| util::parse_rfc3339(sunset).ok()) {
+        Some(sunset) => {
+            api::add_header(response, api::header("Sunset", &util::http_date(sunset)))
+        }
+
+        None => {
+            response
+        }
+    }
+}
+
+fn record_run(config: &config::Config, user: &user::User, language: &language::Language, run_request: run::RunRequest, started: time::SystemTime, counters: &usage::Counters, result: &Result<run::RunResult, api::ErrorResponse>) {
+    let mode = match config.api.run_log.mode {
+        Some(mode) => mode,
+        None => return,
+    };
+
+    let entry = run_log::new(mode, run_log::RunInfo{
+        user_id: user.id,
+        language: &language.name,
+        version: &language.version,
+        started,
+        counters,
+        error: result.as_ref().err().map(|err| err.body.error.clone()),
+        details: run_log::Details{
+            files: run_request.payload.files,
+            stdin: run_request.payload.stdin,
+            command: run_request.payload.command,
+            result: result.as_ref().ok().cloned(),
+        },
+    });
+
+    run_log::record(&config.api.run_log_file, &entry);
+}
+
+fn record_usage(config: &config::Config, user: &user::User, counters: &usage::Counters) {
+    // Usage is written to the datastore periodically so runs don't take the datastore write lock
+    config.api.pending_usage.add(&user.id, time::SystemTime::now(), counters);
+}
+
+// The version is matched against the aliases when no language has the exact version
+fn find_language(data_root: &config::DataRoot, options: &Options) -> Result<language::Language, datastore::GetError> {
+    let language_id = language::id(&options.language, &options.version);
+    let result = datastore::get_entry::<language::Language>(&data_root.languages(), &language_id);
+
+    match result {
+        // The id does not separate name and version, i.e. python39 could be python 39 or python3 9
+        Ok(language) if language.name == options.language && language.version == options.version => {
+            Ok(language)
+        }
+
+        Ok(_) | Err(datastore::GetError::NotFound()) => {
+            let alias_key = language::alias_key(&options.language, &options.version);
+            datastore::find_by_index(&data_root.languages(), &language::ALIAS_INDEX, &alias_key)
+        }
+
+        Err(err) => {
+            Err(err)
+        }
+    }
 }
 
 fn handle_datastore_error(err: datastore::GetError) -> api::ErrorResponse {
@@ -51,6 +181,7 @@ fn handle_datastore_error(err: datastore::GetError) -> api::ErrorResponse {
         datastore::GetError::NotFound() => {
             api::ErrorResponse{
                 status_code: 404,
+                headers: vec![],
                 body: api::ErrorBody{
                     error: "not_found".to_string(),
                     message: "Language or version not found".to_string(),
@@ -61,6 +192,7 @@ fn handle_datastore_error(err: datastore::GetError) -> api::ErrorResponse {
         _ => {
             api::ErrorResponse{
                 status_code: 500,
+                headers: vec![],
                 body: api::ErrorBody{
                     error: "datastore".to_string(),
                     message: err.to_string(),
@@ -71,6 +203,74 @@ fn handle_datastore_error(err: datastore::GetError) -> api::ErrorResponse {
 }
 
 
+fn check_quota(config: &config::Config, data_root: &config::DataRoot, user: &user::User, user_quota: &quota::Quota) -> Result<(), api::ErrorResponse> {
+    let user_usage = match datastore::get_entry::<usage::Usage>(&data_root.usage(), &user.id.to_string()) {
+        Ok(user_usage) => {
+            Ok(user_usage)
+        }
+
+        Err(datastore::GetError::NotFound()) => {
+            Ok(usage::new(&user.id))
+        }
+
+        Err(err) => {
+            Err(api::ErrorResponse{
+                status_code: 500,
+                headers: vec![],
+                body: api::ErrorBody{
+                    error: "datastore".to_string(),
+                    message: err.to_string(),
+                }
+            })
+        }
+    }?;
+
+    // Runs that are not written to the datastore yet count towards the quota
+    let user_usage = config.api.pending_usage.apply(&user_usage, config.api.usage_retention);
+
+    quota::check(user_quota, &user_usage.quota_usage, time::SystemTime::now())
+        .map_err(handle_quota_error)
+}
+
+fn handle_quota_error(err: quota::Error) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 429,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "quota_exceeded".to_string(),
+            message: err.to_string(),
+        }
+    }
+}
+
+fn handle_rate_limit_error(retry_after: time::Duration) -> api::ErrorResponse {
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+
+    api::ErrorResponse{
+        status_code: 429,
+        headers: vec![
+            api::header("Retry-After", &seconds.to_string()),
+        ],
+        body: api::ErrorBody{
+            error: "rate_limit".to_string(),
+            message: format!("Rate limit exceeded, retry in {} seconds", seconds),
+        }
+    }
+}
+
+
+fn handle_concurrency_limit_error(err: concurrency_limit::Error) -> api::ErrorResponse {
+    api::ErrorResponse{
+        status_code: 429,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "concurrency_limit".to_string(),
+            message: err.to_string(),
+        }
+    }
+}
+
+
 // TODO: Send proper status codes
 // 400 is returned in all cases temporarily until we can improve error handling in glot-www
 fn handle_run_error(err: run::Error) -> api::ErrorResponse{
@@ -78,6 +278,7 @@ fn handle_run_error(err: run::Error) -> api::ErrorResponse{
         run::Error::SerializeRequest(serde_err) => {
             api::ErrorResponse{
                 status_code: 400,
+                headers: vec![],
                 body: api::ErrorBody{
                     error: "run.request.body".to_string(),
                     message: format!("Failed to serialize request to docker-run: {}", serde_err)
@@ -88,6 +289,7 @@ fn handle_run_error(err: run::Error) -> api::ErrorResponse{
         run::Error::Request(req_error) => {
             api::ErrorResponse{
                 status_code: 400,
+                headers: vec![],
                 body: api::ErrorBody{
                     error: "run.request.error".to_string(),
                     message: format!("Problem with request to docker-run: {}", req_error)
@@ -98,6 +300,7 @@ fn handle_run_error(err: run::Error) -> api::ErrorResponse{
         run::Error::DeserializeResponse(io_err) => {
             api::ErrorResponse{
                 status_code: 400,
+                headers: vec![],
                 body: api::ErrorBody{
                     error: "run.response.body".to_string(),
                     message: format!("Failed to deserialize response from docker-run: {}", io_err)
@@ -108,6 +311,7 @@ fn handle_run_error(err: run::Error) -> api::ErrorResponse{
         run::Error::DeserializeErrorResponse(io_err) => {
             api::ErrorResponse{
                 status_code: 400,
+                headers: vec![],
                 body: api::ErrorBody{
                     error: "run.response.error.body".to_string(),
                     message: format!("Failed to deserialize error response from docker-run: {}", io_err)
@@ -118,6 +322,7 @@ fn handle_run_error(err: run::Error) -> api::ErrorResponse{
         run::Error::EmptySynthetic() => {
             api::ErrorResponse{
                 status_code: 500,
+                headers: vec![],
                 body: api::ErrorBody{
                     error: "run.response.error.synthetic".to_string(),
                     message: "Request to docker-run failed".to_string(),
@@ -128,6 +333,7 @@ fn handle_run_error(err: run::Error) -> api::ErrorResponse{
         run::Error::ResponseNotOk(error_response) => {
             api::ErrorResponse{
                 status_code: 400,
+                headers: vec![],
                 body: error_response.body,
             }
         }
@@ -135,31 +341,58 @@ fn handle_run_error(err: run::Error) -> api::ErrorResponse{
 }
 
 
-fn check_user(request: &tiny_http::Request, data_root: &config::DataRoot) -> Result<(), api::ErrorResponse> {
-    let auth_token = api::get_auth_token(request).ok_or_else(api::authorization_error)?;
+fn record_token_use(config: &config::Config, user: &user::User, token_id: &uuid::Uuid) {
+    let now = time::SystemTime::now();
+
+    if user::token_used(user, token_id, now).is_none() {
+        return
+    }
+
+    let data_root = config.server.data_root.write().unwrap();
 
-    let _ = datastore::find_value::<_, user::User>(&data_root.users_path(), |user| {
-        user.token == auth_token
-    }).map_err(handle_user_not_found)?;
+    let result = datastore::update_entry::<_, user::User>(&data_root.users(), &user.id.to_string(), |user| {
+        user::token_used(user, token_id, now).unwrap_or_else(|| user.clone())
+    });
 
-    Ok(())
+    if let Err(err) = result {
+        log::error!("Failed to record token use for user {}: {}", user.id, err);
+    }
 }
 
-fn handle_user_not_found(err: datastore::GetError) -> api::ErrorResponse {
-    match err {
-        datastore::GetError::NotFound() => {
-            api::authorization_error()
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(language: &str, version: &str) -> Options {
+        Options{
+            language: language.to_string(),
+            version: version.to_string(),
         }
+    }
 
-        _ => {
-            api::ErrorResponse{
-                status_code: 500,
-                body: api::ErrorBody{
-                    error: "datastore".to_string(),
-                    message: err.to_string(),
-                }
-            }
+    #[test]
+    fn find_language_prefers_exact_version_over_alias() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_root = config::test_data_root(dir.path());
+        let languages = [
+            language::test_language("python", "3.9", &["latest", "3.8"]),
+            language::test_language("python", "3.8", &[]),
+            language::test_language("ruby", "3.0", &["latest"]),
+        ];
+
+        datastore::init(&data_root.languages()).ok().unwrap();
+        for language in &languages {
+            datastore::add_entry(&data_root.languages(), &language.id, language).ok().unwrap();
         }
+
+        let find_version = |language: &str, version: &str| {
+            find_language(&data_root, &options(language, version)).ok().map(|language| language.version)
+        };
+
+        assert_eq!(find_version("python", "3.8"), Some("3.8".to_string()));
+        assert_eq!(find_version("python", "latest"), Some("3.9".to_string()));
+        assert_eq!(find_version("ruby", "latest"), Some("3.0".to_string()));
+        assert_eq!(find_version("ruby", "3.9"), None);
     }
 }
-
@@ -3,15 +3,28 @@ pub mod languages;
 pub mod images;
 pub mod root;
 pub mod not_found;
+pub mod user_auth;
 
 use std::io;
 use std::fmt;
 use std::thread;
+use std::time;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::glot_run::config;
+use crate::glot_run::rate_limit;
+use crate::glot_run::concurrency_limit;
+use crate::glot_run::run_log;
+use crate::glot_run::usage;
+use crate::glot_run::credential;
+use crate::glot_run::datastore;
+use crate::glot_run::token;
+use crate::glot_run::util;
 
 
 
 pub struct ServerConfig<C, H> {
-    pub listen_addr: String,
     pub worker_threads: u16,
     pub handler_config: C,
     pub handler: H,
@@ -99,6 +112,16 @@ impl Workers {
 #[derive(Debug, Clone)]
 pub struct ApiConfig {
     pub admin_access_token: ascii::AsciiString,
+    pub token_hash_key: ascii::AsciiString,
+    pub token_prefix: ascii::AsciiString,
+    pub token_rotation_grace_period: time::Duration,
+    pub rate_limiter: Arc<Mutex<rate_limit::RateLimiter>>,
+    pub concurrency_limiter: Arc<concurrency_limit::ConcurrencyLimiter>,
+    pub run_log: run_log::Config,
+    pub run_log_file: Arc<run_log::RunLog>,
+    // How long daily usage buckets are kept
+    pub usage_retention: time::Duration,
+    pub pending_usage: Arc<usage::Pending>,
 }
 
 pub fn get_auth_token(request: &tiny_http::Request) -> Option<String> {
@@ -111,22 +134,103 @@ pub fn get_auth_token(request: &tiny_http::Request) -> Option<String> {
     })
 }
 
-pub fn check_access_token(access_token: &ascii::AsciiString, request: &tiny_http::Request) -> Result<(), ErrorResponse> {
-    let is_allowed = request.headers().iter()
-        .filter(|header| header.field.equiv("Authorization"))
-        .map(|header| header.value.clone())
-        .any(|value| value == format!("Token {}", access_token));
+pub fn get_query_params(request: &tiny_http::Request) -> Vec<(String, String)> {
+    let query = request.url()
+        .split_once('?')
+        .map(|(_, query)| query)
+        .unwrap_or("");
+
+    url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect()
+}
+
+// Entity tag of a user or language revision
+pub fn etag(revision: u64) -> String {
+    format!("\"{}\"", revision)
+}
 
-    if is_allowed {
-        Ok(())
-    } else {
-        Err(authorization_error())
+// Requests without an If-Match header are always allowed.
+// If-Match uses strong comparison, so weak tags (W/"...") never match
+pub fn check_if_match(request: &tiny_http::Request, revision: u64) -> Result<(), ErrorResponse> {
+    let if_match = request.headers().iter().find_map(|header| {
+        if header.field.equiv("If-Match") {
+            Some(header.value.to_string())
+        } else {
+            None
+        }
+    });
+
+    let current_etag = etag(revision);
+
+    let is_match = if_match
+        .map(|value| if_match_matches(&value, &current_etag))
+        .unwrap_or(true);
+
+    util::err_if_false(is_match, ErrorResponse{
+        status_code: 412,
+        headers: vec![header("ETag", &current_etag)],
+        body: ErrorBody{
+            error: "precondition_failed".to_string(),
+            message: format!("The resource has been modified, the current revision is {}", revision),
+        }
+    })
+}
+
+fn if_match_matches(if_match: &str, current_etag: &str) -> bool {
+    if_match.split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag == current_etag)
+}
+
+pub fn check_access_token(config: &config::Config, request: &tiny_http::Request, scope: credential::Scope) -> Result<credential::Actor, ErrorResponse> {
+    let auth_token = get_auth_token(request).ok_or_else(authorization_error)?;
+
+    // The admin access token from the environment is allowed everything
+    if token::hash_eq(&auth_token, config.api.admin_access_token.as_str()) {
+        return Ok(credential::admin_access_token_actor())
+    }
+
+    let token_hash = token::hash(&config.api.token_hash_key, &auth_token);
+    let data_root = config.server.data_root.read().unwrap();
+    let credential = datastore::find_by_index::<credential::Credential>(&data_root.credentials(), &credential::TOKEN_HASH_INDEX, &token_hash)
+        .map_err(handle_credential_not_found)?;
+
+    util::err_if_false(credential::has_scope(credential.role, scope), ErrorResponse{
+        status_code: 403,
+        headers: vec![],
+        body: ErrorBody{
+            error: "access_token.scope".to_string(),
+            message: "Access token is not allowed to perform this action".to_string(),
+        }
+    })?;
+
+    Ok(credential::to_actor(&credential))
+}
+
+fn handle_credential_not_found(err: datastore::GetError) -> ErrorResponse {
+    match err {
+        datastore::GetError::NotFound() => {
+            authorization_error()
+        }
+
+        _ => {
+            ErrorResponse{
+                status_code: 500,
+                headers: vec![],
+                body: ErrorBody{
+                    error: "datastore".to_string(),
+                    message: err.to_string(),
+                }
+            }
+        }
     }
 }
 
 pub fn authorization_error() -> ErrorResponse {
     ErrorResponse{
         status_code: 401,
+        headers: vec![],
         body: ErrorBody{
             error: "access_token".to_string(),
             message: "Missing or wrong access token".to_string(),
@@ -138,6 +242,7 @@ pub fn read_json_body<T: serde::de::DeserializeOwned>(request: &mut tiny_http::R
     serde_json::from_reader(request.as_reader())
         .map_err(|err| ErrorResponse{
             status_code: 400,
+            headers: vec![],
             body: ErrorBody{
                 error: "request.parse".to_string(),
                 message: format!("Failed to parse json from request: {}", err),
@@ -145,6 +250,10 @@ pub fn read_json_body<T: serde::de::DeserializeOwned>(request: &mut tiny_http::R
         })
 }
 
+pub fn header(name: &str, value: &str) -> tiny_http::Header {
+    tiny_http::Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
 fn send_response(request: tiny_http::Request, response: Result<SuccessResponse, ErrorResponse>) {
     let result = match response {
         Ok(data) => {
@@ -167,13 +276,25 @@ fn send_response(request: tiny_http::Request, response: Result<SuccessResponse,
 
 pub struct SuccessResponse {
     status_code: u16,
+    headers: Vec<tiny_http::Header>,
     body: Vec<u8>,
 }
 
+pub fn add_header(response: SuccessResponse, header: tiny_http::Header) -> SuccessResponse {
+    let mut headers = response.headers;
+    headers.push(header);
+
+    SuccessResponse{
+        headers,
+        ..response
+    }
+}
+
 
 pub fn prepare_empty_response() -> SuccessResponse {
     SuccessResponse{
         status_code: 204,
+        headers: vec![],
         body: vec![],
     }
 }
@@ -183,6 +304,7 @@ pub fn prepare_json_response<T: serde::Serialize>(body: &T) -> Result<SuccessRes
         Ok(data) => {
             Ok(SuccessResponse{
                 status_code: 200,
+                headers: vec![],
                 body: data,
             })
         }
@@ -190,6 +312,7 @@ pub fn prepare_json_response<T: serde::Serialize>(body: &T) -> Result<SuccessRes
         Err(err) => {
             Err(ErrorResponse{
                 status_code: 500,
+                headers: vec![],
                 body: ErrorBody{
                     error: "response.serialize".to_string(),
                     message: format!("Failed to serialize response: {}", err),
@@ -203,11 +326,15 @@ pub fn prepare_json_response<T: serde::Serialize>(body: &T) -> Result<SuccessRes
 pub fn success_response(request: tiny_http::Request, data: &SuccessResponse) -> Result<(), io::Error> {
     let body = data.body.as_slice();
 
+    let mut headers = vec![
+        tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap()
+    ];
+
+    headers.extend(data.headers.iter().cloned());
+
     let response = tiny_http::Response::new(
         tiny_http::StatusCode(data.status_code),
-        vec![
-            tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap()
-        ],
+        headers,
         body,
         Some(body.len()),
         None,
@@ -220,11 +347,15 @@ pub fn error_response(request: tiny_http::Request, error: ErrorResponse) -> Resu
     let error_body = serde_json::to_vec_pretty(&error.body)
         .unwrap_or_else(|_| b"Failed to serialize error body".to_vec());
 
+    let mut headers = vec![
+        tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap()
+    ];
+
+    headers.extend(error.headers);
+
     let response = tiny_http::Response::new(
         tiny_http::StatusCode(error.status_code),
-        vec![
-            tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap()
-        ],
+        headers,
         error_body.as_slice(),
         Some(error_body.len()),
         None,
@@ -251,6 +382,7 @@ impl fmt::Display for Error {
 #[derive(Debug)]
 pub struct ErrorResponse {
     pub status_code: u16,
+    pub headers: Vec<tiny_http::Header>,
     pub body: ErrorBody,
 }
 
@@ -261,3 +393,38 @@ pub struct ErrorBody {
     pub message: String,
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn etag_is_quoted_revision() {
+        assert_eq!(etag(3), "\"3\"");
+    }
+
+    #[test]
+    fn if_match_matches_current_etag() {
+        assert!(if_match_matches("\"3\"", &etag(3)));
+        assert!(!if_match_matches("\"2\"", &etag(3)));
+        assert!(!if_match_matches("3", &etag(3)));
+    }
+
+    #[test]
+    fn if_match_matches_any_tag_in_list() {
+        assert!(if_match_matches("\"1\", \"3\"", &etag(3)));
+        assert!(if_match_matches("\"1\",\"3\"", &etag(3)));
+        assert!(!if_match_matches("\"1\", \"2\"", &etag(3)));
+    }
+
+    #[test]
+    fn if_match_matches_wildcard() {
+        assert!(if_match_matches("*", &etag(3)));
+    }
+
+    #[test]
+    fn if_match_never_matches_weak_tags() {
+        assert!(!if_match_matches("W/\"3\"", &etag(3)));
+        assert!(!if_match_matches("W/\"1\", W/\"3\"", &etag(3)));
+    }
+}
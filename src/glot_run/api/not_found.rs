@@ -7,6 +7,7 @@ pub fn handle(_: &config::Config, _: &mut tiny_http::Request) -> Result<api::Suc
 
     Err(api::ErrorResponse{
         status_code: 404,
+        headers: vec![],
         body: api::ErrorBody{
             error: "route.not_found".to_string(),
             message: "Route not found".to_string(),
@@ -0,0 +1,76 @@
+use std::time;
+
+use crate::glot_run::config;
+use crate::glot_run::api;
+use crate::glot_run::user;
+use crate::glot_run::datastore;
+use crate::glot_run::token;
+use crate::glot_run::util;
+
+
+
+pub fn check_user(api_config: &api::ApiConfig, request: &tiny_http::Request, data_root: &config::DataRoot) -> Result<(user::User, uuid::Uuid), api::ErrorResponse> {
+    let auth_token = api::get_auth_token(request).ok_or_else(api::authorization_error)?;
+    let token_hash = token::hash(&api_config.token_hash_key, &auth_token);
+
+    let user = datastore::find_by_index::<user::User>(&data_root.users(), &user::TOKEN_HASH_INDEX, &token_hash)
+        .map_err(handle_user_not_found)?;
+
+    let token = user::find_token(&user, &token_hash)
+        .ok_or_else(api::authorization_error)?;
+
+    util::err_if_false(!user::is_expired(token, time::SystemTime::now()), api::ErrorResponse{
+        status_code: 401,
+        headers: vec![],
+        body: api::ErrorBody{
+            error: "access_token.expired".to_string(),
+            message: "Access token has expired".to_string(),
+        }
+    })?;
+
+    if let Some(suspension) = &user.suspension {
+        return Err(api::ErrorResponse{
+            status_code: 403,
+            headers: vec![],
+            body: api::ErrorBody{
+                error: "user_suspended".to_string(),
+                message: format!("User has been suspended since {}: {}", suspension.since, suspension.reason),
+            }
+        })
+    }
+
+    let token_id = token.id;
+
+    Ok((user, token_id))
+}
+
+// Returns None when the request has no authorization header
+pub fn check_optional_user(api_config: &api::ApiConfig, request: &tiny_http::Request, data_root: &config::DataRoot) -> Result<Option<user::User>, api::ErrorResponse> {
+    if api::get_auth_token(request).is_none() {
+        return Ok(None)
+    }
+
+    let (user, _) = check_user(api_config, request, data_root)?;
+
+    Ok(Some(user))
+}
+
+fn handle_user_not_found(err: datastore::GetError) -> api::ErrorResponse {
+    match err {
+        datastore::GetError::NotFound() => {
+            api::authorization_error()
+        }
+
+        _ => {
+            api::ErrorResponse{
+                status_code: 500,
+                headers: vec![],
+                body: api::ErrorBody{
+                    error: "datastore".to_string(),
+                    message: err.to_string(),
+                }
+            }
+        }
+    }
+}
+
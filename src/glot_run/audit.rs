@@ -0,0 +1,88 @@
+use std::time;
+
+use crate::glot_run::config;
+use crate::glot_run::credential;
+use crate::glot_run::file;
+use crate::glot_run::util;
+
+
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Event {
+    pub id: uuid::Uuid,
+    pub timestamp: String,
+    pub actor: credential::Actor,
+    pub action: String,
+    pub target_id: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+
+// Fields that are never recorded, token hashes of users and credentials
+const SECRET_FIELDS: [&str; 2] = ["hash", "tokenHash"];
+
+
+pub fn new<T: serde::Serialize>(actor: &credential::Actor, action: &str, target_id: &str, before: Option<&T>, after: Option<&T>) -> Event {
+    let now = time::SystemTime::now();
+
+    Event{
+        id: uuid::Uuid::new_v4(),
+        timestamp: util::rfc3339(now),
+        actor: actor.clone(),
+        action: action.to_string(),
+        target_id: target_id.to_string(),
+        before: before.and_then(|value| serde_json::to_value(value).ok()).map(redact),
+        after: after.and_then(|value| serde_json::to_value(value).ok()).map(redact),
+    }
+}
+
+// Events recorded before secrets were redacted still contain them
+pub fn redact_event(event: Event) -> Event {
+    Event{
+        before: event.before.map(redact),
+        after: event.after.map(redact),
+        ..event
+    }
+}
+
+fn redact(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(fields) => {
+            let fields = fields.into_iter()
+                .filter(|(key, _)| !SECRET_FIELDS.contains(&key.as_str()))
+                .map(|(key, value)| (key, redact(value)))
+                .collect();
+
+            serde_json::Value::Object(fields)
+        }
+
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(redact).collect())
+        }
+
+        _ => {
+            value
+        }
+    }
+}
+
+// The mutation has already happened when the event is recorded, so failures are only logged
+pub fn record(data_root: &config::DataRoot, event: &Event) {
+    if let Err(err) = file::append_json_line(&data_root.audit_log_path(), event) {
+        log::error!("Failed to record audit event {} for {}: {}", event.action, event.target_id, err);
+    }
+}
+
+pub fn is_within(event: &Event, from: Option<time::SystemTime>, to: Option<time::SystemTime>) -> bool {
+    match util::parse_rfc3339(&event.timestamp) {
+        Ok(timestamp) => {
+            from.map(|from| timestamp >= from).unwrap_or(true) && to.map(|to| timestamp < to).unwrap_or(true)
+        }
+
+        Err(_) => {
+            false
+        }
+    }
+}
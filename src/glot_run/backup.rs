@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time;
+
+use crate::glot_run::file;
+use crate::glot_run::util;
+
+
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub dir: PathBuf,
+    // Number of backups to keep of each file, backups are disabled when this is 0
+    pub count: usize,
+    // Minimum time between two backups of the same file
+    pub interval: time::Duration,
+}
+
+
+// Backup config and the time of the newest backup of each file, so the backup dir is only read when a backup is due.
+// A backup removed by something else than glot-run can delay the next backup by one interval
+#[derive(Debug)]
+pub struct Rotation {
+    config: Config,
+    last_backups: Mutex<HashMap<String, time::SystemTime>>,
+}
+
+impl Rotation {
+    pub fn new(config: Config) -> Rotation {
+        Rotation{
+            config,
+            last_backups: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+
+// Backups are named <file stem>.<timestamp>.<extension>, i.e. users.20210101T120000Z.json
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Backup {
+    pub name: String,
+    pub file_name: String,
+    pub created: String,
+}
+
+
+// Copies the file to the backup dir when the newest backup of it is older than the interval,
+// copy_fn writes a consistent copy of the file to the given backup path
+pub fn rotate<F>(rotation: &Rotation, path: &Path, now: time::SystemTime, copy_fn: F) -> Result<(), Error>
+    where F: FnOnce(&Path) -> Result<(), io::Error> {
+
+    let config = &rotation.config;
+
+    if config.count == 0 || !path.exists() {
+        return Ok(())
+    }
+
+    let (stem, extension) = split_file_name(path)
+        .ok_or(Error::InvalidFileName())?;
+
+    let file_name = format!("{}.{}", stem, extension);
+    let mut last_backups = rotation.last_backups.lock().unwrap();
+
+    let last_backup = match last_backups.get(&file_name) {
+        Some(created) => {
+            Some(*created)
+        }
+
+        None => {
+            newest_backup(config, &file_name)?
+        }
+    };
+
+    if let Some(created) = last_backup {
+        last_backups.insert(file_name.clone(), created);
+    }
+
+    let is_due = last_backup
+        .map(|created| created + config.interval <= now)
+        .unwrap_or(true);
+
+    if !is_due {
+        return Ok(())
+    }
+
+    let backups = list_for(config, &file_name)?;
+
+    fs::create_dir_all(&config.dir)
+        .map_err(Error::Io)?;
+
+    let backup_name = format!("{}.{}.{}", stem, util::compact_timestamp(now), extension);
+
+    // Names have second granularity, the existing backup is kept when there already is one from this second
+    if backups.iter().any(|backup| backup.name == backup_name) {
+        last_backups.insert(file_name, now);
+        return Ok(())
+    }
+
+    copy_fn(&config.dir.join(&backup_name))
+        .map_err(Error::Io)?;
+
+    last_backups.insert(file_name, now);
+
+    // The new backup is not in the list, so it is never removed here
+    let excess = (backups.len() + 1).saturating_sub(config.count);
+
+    for backup in backups.iter().take(excess) {
+        fs::remove_file(config.dir.join(&backup.name))
+            .map_err(Error::Io)?;
+    }
+
+    Ok(())
+}
+
+// Returns all backups sorted by file name and creation time
+pub fn list(config: &Config) -> Result<Vec<Backup>, Error> {
+    if !config.dir.exists() {
+        return Ok(vec![])
+    }
+
+    let mut backups = Vec::new();
+
+    for dir_entry in fs::read_dir(&config.dir).map_err(Error::Io)? {
+        let dir_entry = dir_entry.map_err(Error::Io)?;
+
+        if let Some(backup) = dir_entry.file_name().to_str().and_then(parse_name) {
+            backups.push(backup);
+        }
+    }
+
+    backups.sort_by(|a, b| (&a.file_name, &a.created).cmp(&(&b.file_name, &b.created)));
+
+    Ok(backups)
+}
+
+// Replaces the original file in root with the backup
+pub fn restore(config: &Config, root: &Path, name: &str) -> Result<Backup, Error> {
+    let backup = list(config)?
+        .into_iter()
+        .find(|backup| backup.name == name)
+        .ok_or(Error::NotFound())?;
+
+    file::copy_durable(&config.dir.join(&backup.name), &root.join(&backup.file_name))
+        .map_err(Error::Io)?;
+
+    Ok(backup)
+}
+
+fn list_for(config: &Config, file_name: &str) -> Result<Vec<Backup>, Error> {
+    let backups = list(config)?
+        .into_iter()
+        .filter(|backup| backup.file_name == file_name)
+        .collect();
+
+    Ok(backups)
+}
+
+fn newest_backup(config: &Config, file_name: &str) -> Result<Option<time::SystemTime>, Error> {
+    let backups = list_for(config, file_name)?;
+
+    let created = backups.last()
+        .and_then(|backup| util::parse_rfc3339(&backup.created).ok());
+
+    Ok(created)
+}
+
+fn split_file_name(path: &Path) -> Option<(&str, &str)> {
+    let stem = path.file_stem()?.to_str()?;
+    let extension = path.extension()?.to_str()?;
+
+    Some((stem, extension))
+}
+
+fn parse_name(name: &str) -> Option<Backup> {
+    let (rest, extension) = name.rsplit_once('.')?;
+    let (stem, timestamp) = rest.rsplit_once('.')?;
+    let created = util::parse_compact_timestamp(timestamp).ok()?;
+
+    Some(Backup{
+        name: name.to_string(),
+        file_name: format!("{}.{}", stem, extension),
+        created: util::rfc3339(created),
+    })
+}
+
+
+pub enum Error {
+    InvalidFileName(),
+    NotFound(),
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidFileName() => {
+                write!(f, "Invalid file name")
+            }
+
+            Error::NotFound() => {
+                write!(f, "Backup not found")
+            }
+
+            Error::Io(err) => {
+                write!(f, "{}", err)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: time::Duration = time::Duration::from_secs(60 * 60);
+
+    fn rotation(dir: &tempfile::TempDir, count: usize) -> Rotation {
+        Rotation::new(Config{
+            dir: dir.path().join("backups"),
+            count,
+            interval: INTERVAL,
+        })
+    }
+
+    fn at(secs: u64) -> time::SystemTime {
+        time::UNIX_EPOCH + time::Duration::from_secs(1_600_000_000 + secs)
+    }
+
+    fn rotate_ok(rotation: &Rotation, path: &Path, now: time::SystemTime) {
+        rotate(rotation, path, now, |backup_path| {
+            fs::copy(path, backup_path).map(|_| ())
+        }).ok().unwrap();
+    }
+
+    fn backup_names(rotation: &Rotation) -> Vec<String> {
+        list(&rotation.config).ok().unwrap()
+            .into_iter()
+            .map(|backup| backup.name)
+            .collect()
+    }
+
+    #[test]
+    fn parse_name_splits_stem_timestamp_and_extension() {
+        let backup = parse_name("users.20200913T122640Z.json").unwrap();
+
+        assert_eq!(backup.file_name, "users.json");
+        assert_eq!(util::parse_rfc3339(&backup.created).ok(), Some(at(0)));
+        assert!(parse_name("users.json").is_none());
+        assert!(parse_name("users.yesterday.json").is_none());
+    }
+
+    #[test]
+    fn no_backup_within_interval() {
+        let dir = tempfile::tempdir().unwrap();
+        let rotation = rotation(&dir, 3);
+        let path = dir.path().join("users.json");
+        fs::write(&path, "{}").unwrap();
+
+        rotate_ok(&rotation, &path, at(0));
+        rotate_ok(&rotation, &path, at(INTERVAL.as_secs() - 1));
+
+        assert_eq!(backup_names(&rotation), vec!["users.20200913T122640Z.json"]);
+    }
+
+    #[test]
+    fn last_backup_time_is_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let rotation = rotation(&dir, 3);
+        let path = dir.path().join("users.json");
+        fs::write(&path, "{}").unwrap();
+
+        rotate_ok(&rotation, &path, at(0));
+        fs::remove_dir_all(dir.path().join("backups")).unwrap();
+        rotate_ok(&rotation, &path, at(1));
+
+        assert!(backup_names(&rotation).is_empty());
+    }
+
+    #[test]
+    fn backup_after_interval() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.json");
+        fs::write(&path, "{}").unwrap();
+
+        rotate_ok(&rotation(&dir, 3), &path, at(0));
+
+        // A new rotation reads the time of the last backup from the backup dir
+        let rotation = rotation(&dir, 3);
+        rotate_ok(&rotation, &path, at(INTERVAL.as_secs() - 1));
+        assert_eq!(backup_names(&rotation).len(), 1);
+
+        rotate_ok(&rotation, &path, at(INTERVAL.as_secs()));
+        assert_eq!(backup_names(&rotation), vec!["users.20200913T122640Z.json", "users.20200913T132640Z.json"]);
+    }
+
+    #[test]
+    fn count_removes_only_the_oldest_backups_of_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let rotation = rotation(&dir, 2);
+        let users_path = dir.path().join("users.json");
+        let languages_path = dir.path().join("languages.json");
+        fs::write(&users_path, "{}").unwrap();
+        fs::write(&languages_path, "{}").unwrap();
+
+        rotate_ok(&rotation, &languages_path, at(0));
+
+        for hour in 0..3 {
+            rotate_ok(&rotation, &users_path, at(hour * INTERVAL.as_secs()));
+        }
+
+        assert_eq!(backup_names(&rotation), vec![
+            "languages.20200913T122640Z.json",
+            "users.20200913T132640Z.json",
+            "users.20200913T142640Z.json",
+        ]);
+    }
+
+    #[test]
+    fn restore_replaces_file_and_rejects_unknown_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let rotation = rotation(&dir, 3);
+        let path = dir.path().join("users.json");
+        fs::write(&path, "{\"a\": 1}").unwrap();
+
+        rotate_ok(&rotation, &path, at(0));
+        fs::write(&path, "{}").unwrap();
+
+        assert!(matches!(restore(&rotation.config, dir.path(), "users.20200913T132640Z.json"), Err(Error::NotFound())));
+        assert!(matches!(restore(&rotation.config, dir.path(), "users.json"), Err(Error::NotFound())));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+
+        let backup = restore(&rotation.config, dir.path(), "users.20200913T122640Z.json").ok().unwrap();
+        assert_eq!(backup.file_name, "users.json");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"a\": 1}");
+    }
+}
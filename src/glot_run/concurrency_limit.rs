@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Condvar;
+use std::time;
+use std::fmt;
+
+
+
+#[derive(Debug)]
+pub struct ConcurrencyLimiter {
+    default_limit: Option<u32>,
+    max_wait: time::Duration,
+    running: Mutex<HashMap<uuid::Uuid, u32>>,
+    released: Condvar,
+}
+
+
+// Holds a run slot for a user, the slot is released when the permit is dropped
+pub struct Permit {
+    limiter: Arc<ConcurrencyLimiter>,
+    user_id: uuid::Uuid,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limiter.release(&self.user_id);
+    }
+}
+
+
+impl ConcurrencyLimiter {
+    pub fn new(default_limit: Option<u32>, max_wait: time::Duration) -> ConcurrencyLimiter {
+        ConcurrencyLimiter{
+            default_limit,
+            max_wait,
+            running: Mutex::new(HashMap::new()),
+            released: Condvar::new(),
+        }
+    }
+
+    pub fn acquire(limiter: &Arc<ConcurrencyLimiter>, user_id: &uuid::Uuid, user_limit: Option<u32>) -> Result<Option<Permit>, Error> {
+        let limit = match user_limit.or(limiter.default_limit) {
+            Some(limit) => limit,
+            None => return Ok(None),
+        };
+
+        let deadline = time::Instant::now() + limiter.max_wait;
+        let mut running = limiter.running.lock().unwrap();
+
+        loop {
+            let count = running.get(user_id).copied().unwrap_or(0);
+
+            if count < limit {
+                running.insert(*user_id, count + 1);
+
+                return Ok(Some(Permit{
+                    limiter: Arc::clone(limiter),
+                    user_id: *user_id,
+                }))
+            }
+
+            let now = time::Instant::now();
+            if now >= deadline {
+                return Err(Error::LimitReached(limit))
+            }
+
+            running = limiter.released.wait_timeout(running, deadline - now).unwrap().0;
+        }
+    }
+
+    fn release(&self, user_id: &uuid::Uuid) {
+        let mut running = self.running.lock().unwrap();
+
+        if let Some(count) = running.get_mut(user_id) {
+            *count -= 1;
+
+            if *count == 0 {
+                running.remove(user_id);
+            }
+        }
+
+        // Unlock mutex
+        drop(running);
+
+        self.released.notify_all();
+    }
+}
+
+
+pub enum Error {
+    LimitReached(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::LimitReached(limit) => {
+                write!(f, "Too many concurrent runs, the limit is {}", limit)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(default_limit: Option<u32>) -> Arc<ConcurrencyLimiter> {
+        Arc::new(ConcurrencyLimiter::new(default_limit, time::Duration::from_millis(10)))
+    }
+
+    #[test]
+    fn no_permit_without_a_limit() {
+        let limiter = limiter(None);
+        let user_id = uuid::Uuid::new_v4();
+
+        assert!(matches!(ConcurrencyLimiter::acquire(&limiter, &user_id, None), Ok(None)));
+    }
+
+    #[test]
+    fn rejects_when_limit_is_reached() {
+        let limiter = limiter(Some(2));
+        let user_id = uuid::Uuid::new_v4();
+
+        let _first = ConcurrencyLimiter::acquire(&limiter, &user_id, None).ok().unwrap();
+        let _second = ConcurrencyLimiter::acquire(&limiter, &user_id, None).ok().unwrap();
+
+        assert!(matches!(ConcurrencyLimiter::acquire(&limiter, &user_id, None), Err(Error::LimitReached(2))));
+    }
+
+    #[test]
+    fn dropping_a_permit_releases_the_slot() {
+        let limiter = limiter(Some(1));
+        let user_id = uuid::Uuid::new_v4();
+
+        let permit = ConcurrencyLimiter::acquire(&limiter, &user_id, None).ok().unwrap();
+        assert!(ConcurrencyLimiter::acquire(&limiter, &user_id, None).is_err());
+
+        drop(permit);
+        assert!(ConcurrencyLimiter::acquire(&limiter, &user_id, None).is_ok());
+        assert!(limiter.running.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn waiting_run_gets_released_slot() {
+        let limiter = Arc::new(ConcurrencyLimiter::new(Some(1), time::Duration::from_secs(5)));
+        let user_id = uuid::Uuid::new_v4();
+
+        let permit = ConcurrencyLimiter::acquire(&limiter, &user_id, None).ok().unwrap();
+
+        let waiter = {
+            let limiter = Arc::clone(&limiter);
+            std::thread::spawn(move || ConcurrencyLimiter::acquire(&limiter, &user_id, None).is_ok())
+        };
+
+        std::thread::sleep(time::Duration::from_millis(50));
+        drop(permit);
+
+        assert!(waiter.join().unwrap());
+    }
+
+    #[test]
+    fn user_limit_overrides_default() {
+        let limiter = limiter(Some(1));
+        let user_id = uuid::Uuid::new_v4();
+
+        let _first = ConcurrencyLimiter::acquire(&limiter, &user_id, Some(2)).ok().unwrap();
+        let _second = ConcurrencyLimiter::acquire(&limiter, &user_id, Some(2)).ok().unwrap();
+
+        assert!(ConcurrencyLimiter::acquire(&limiter, &user_id, Some(2)).is_err());
+    }
+}
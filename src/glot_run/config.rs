@@ -1,9 +1,15 @@
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::RwLock;
 
 use crate::glot_run::api;
 use crate::glot_run::run;
+use crate::glot_run::datastore;
+use crate::glot_run::storage;
+use crate::glot_run::backup;
+use crate::glot_run::user;
+use crate::glot_run::credential;
+use crate::glot_run::language;
 
 #[derive(Clone, Debug)]
 pub struct Config {
@@ -19,7 +25,7 @@ pub struct ServerConfig {
     pub listen_port: u16,
     pub worker_threads: u16,
     pub base_url: String,
-    pub data_root: Arc<Mutex<DataRoot>>,
+    pub data_root: Arc<RwLock<DataRoot>>,
 }
 
 impl ServerConfig {
@@ -30,24 +36,75 @@ impl ServerConfig {
 
 
 
+// Names of the datastore tables
+pub const USERS_TABLE: &str = "users";
+pub const LANGUAGES_TABLE: &str = "languages";
+pub const USAGE_TABLE: &str = "usage";
+pub const CREDENTIALS_TABLE: &str = "credentials";
+
+pub const TABLES: [&str; 4] = [USERS_TABLE, LANGUAGES_TABLE, USAGE_TABLE, CREDENTIALS_TABLE];
+
+// Secondary indexes of the tables
+pub const INDEXES: [(&str, storage::Index); 3] = [
+    (USERS_TABLE, user::TOKEN_HASH_INDEX),
+    (CREDENTIALS_TABLE, credential::TOKEN_HASH_INDEX),
+    (LANGUAGES_TABLE, language::ALIAS_INDEX),
+];
+
+
 #[derive(Clone, Debug)]
-pub struct DataRoot(PathBuf);
+pub struct DataRoot {
+    path: PathBuf,
+    storage: Arc<dyn storage::Storage>,
+}
 
 
 impl DataRoot {
-    pub fn new(path: PathBuf) -> DataRoot {
-        DataRoot(path)
+    pub fn new(path: PathBuf, backend: storage::Backend, backup_config: backup::Config) -> DataRoot {
+        let storage = storage::new(backend, &path, backup_config, &INDEXES);
+
+        DataRoot{
+            path,
+            storage,
+        }
     }
 
     pub fn root_path(&self) -> PathBuf {
-        self.0.clone()
+        self.path.clone()
+    }
+
+    pub fn users(&self) -> datastore::Table {
+        datastore::Table::new(&self.storage, USERS_TABLE)
     }
 
-    pub fn users_path(&self) -> PathBuf {
-        self.0.join("users.json")
+    pub fn languages(&self) -> datastore::Table {
+        datastore::Table::new(&self.storage, LANGUAGES_TABLE)
     }
 
-    pub fn languages_path(&self) -> PathBuf {
-        self.0.join("languages.json")
+    pub fn usage(&self) -> datastore::Table {
+        datastore::Table::new(&self.storage, USAGE_TABLE)
     }
+
+    pub fn credentials(&self) -> datastore::Table {
+        datastore::Table::new(&self.storage, CREDENTIALS_TABLE)
+    }
+
+    pub fn audit_log_path(&self) -> PathBuf {
+        self.path.join("audit.jsonl")
+    }
+
+    pub fn run_log_path(&self) -> PathBuf {
+        self.path.join("runs.jsonl")
+    }
+}
+
+
+// A json file data root with backups disabled, for tests
+#[cfg(test)]
+pub(crate) fn test_data_root(path: &std::path::Path) -> DataRoot {
+    DataRoot::new(path.to_path_buf(), storage::Backend::JsonFile, backup::Config{
+        dir: path.join("backups"),
+        count: 0,
+        interval: std::time::Duration::from_secs(0),
+    })
 }
@@ -0,0 +1,132 @@
+use std::time;
+
+use crate::glot_run::util;
+use crate::glot_run::storage;
+
+
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Credential {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub role: Role,
+    pub token_hash: String,
+    pub created: String,
+    pub modified: String,
+}
+
+
+// A credential as returned by the api, the token hash is only stored
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicCredential<'a> {
+    pub id: &'a uuid::Uuid,
+    pub name: &'a str,
+    pub role: Role,
+    pub created: &'a str,
+    pub modified: &'a str,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    ReadOnly,
+    UserManager,
+    LanguageManager,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Read,
+    ManageUsers,
+    ManageLanguages,
+    // Only allowed for the admin access token from the environment
+    ManageCredentials,
+    // Files and outputs of logged runs, only allowed for the admin access token from the environment
+    ReadRunDetails,
+    // Only allowed for the admin access token from the environment
+    Export,
+    // Only allowed for the admin access token from the environment
+    Import,
+}
+
+
+// Who performed an admin request, credential_id is None for the admin access token from the environment
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Actor {
+    pub credential_id: Option<uuid::Uuid>,
+    pub name: String,
+}
+
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialData {
+    pub name: String,
+    pub role: Role,
+}
+
+
+// Index of credentials by token hash
+pub const TOKEN_HASH_INDEX: storage::Index = storage::Index{
+    name: "tokenHash",
+    keys: token_hash,
+};
+
+
+pub fn new(data: &CredentialData, token_hash: String) -> Credential {
+    let id = uuid::Uuid::new_v4();
+    let now = time::SystemTime::now();
+
+    Credential{
+        id,
+        name: data.name.clone(),
+        role: data.role,
+        token_hash,
+        created: util::rfc3339(now),
+        modified: util::rfc3339(now),
+    }
+}
+
+pub fn to_public(credential: &Credential) -> PublicCredential<'_> {
+    PublicCredential{
+        id: &credential.id,
+        name: &credential.name,
+        role: credential.role,
+        created: &credential.created,
+        modified: &credential.modified,
+    }
+}
+
+fn token_hash(entry: &serde_json::Value) -> Vec<String> {
+    entry.get("tokenHash")
+        .and_then(|hash| hash.as_str())
+        .map(|hash| vec![hash.to_string()])
+        .unwrap_or_default()
+}
+
+pub fn to_actor(credential: &Credential) -> Actor {
+    Actor{
+        credential_id: Some(credential.id),
+        name: credential.name.clone(),
+    }
+}
+
+pub fn admin_access_token_actor() -> Actor {
+    Actor{
+        credential_id: None,
+        name: "admin_access_token".to_string(),
+    }
+}
+
+pub fn has_scope(role: Role, scope: Scope) -> bool {
+    matches!((role, scope),
+        (_, Scope::Read) |
+        (Role::UserManager, Scope::ManageUsers) |
+        (Role::LanguageManager, Scope::ManageLanguages)
+    )
+}
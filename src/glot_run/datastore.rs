@@ -1,25 +1,46 @@
 use std::collections::HashMap;
-use std::path::Path;
+use std::sync::Arc;
 use std::fmt;
 
-use crate::glot_run::file;
+use crate::glot_run::storage;
 
 
 
-type DataStore<E> = HashMap<String, E>;
+// A named table in the configured storage backend
+#[derive(Debug, Clone)]
+pub struct Table {
+    storage: Arc<dyn storage::Storage>,
+    name: &'static str,
+}
 
+impl Table {
+    pub fn new(storage: &Arc<dyn storage::Storage>, name: &'static str) -> Table {
+        Table{
+            storage: Arc::clone(storage),
+            name,
+        }
+    }
 
-pub fn init<E: serde::Serialize>(path: &Path) -> Result<(), file::WriteJsonError> {
-    if !path.exists() {
-        let entries: DataStore<E> = HashMap::new();
-        file::write_json(path, &entries)?;
+    pub fn name(&self) -> &'static str {
+        self.name
     }
+}
+
+
+pub fn init(table: &Table) -> Result<(), storage::Error> {
+    table.storage.init(table.name)
+}
+
+pub fn exists(table: &Table) -> Result<bool, storage::Error> {
+    table.storage.exists(table.name)
+}
 
-    Ok(())
+pub fn schema_version(table: &Table) -> Result<u32, storage::Error> {
+    table.storage.schema_version(table.name)
 }
 
 pub enum GetError {
-    Read(file::ReadJsonError),
+    Read(storage::Error),
     NotFound(),
 }
 
@@ -37,59 +58,48 @@ impl fmt::Display for GetError {
     }
 }
 
-pub fn get_entry<E>(path: &Path, key: &str) -> Result<E, GetError>
+pub fn get_entry<E>(table: &Table, key: &str) -> Result<E, GetError>
     where
         E: Clone,
         E: serde::de::DeserializeOwned {
 
-    let entries: DataStore<E> = file::read_json(path)
-        .map_err(GetError::Read)?;
-
-    let entry = entries.get(key)
+    let value = table.storage.get(table.name, key)
+        .map_err(GetError::Read)?
         .ok_or(GetError::NotFound())?;
 
-    Ok(entry.clone())
+    from_value(value)
+        .map_err(GetError::Read)
 }
 
-pub fn list_values<E>(path: &Path) -> Result<Vec<E>, file::ReadJsonError>
+pub fn list_values<E>(table: &Table) -> Result<Vec<E>, storage::Error>
     where
         E: Clone,
         E: serde::de::DeserializeOwned {
 
-    let entries: DataStore<E> = file::read_json(path)?;
-
-    let values = entries.values()
-        .cloned()
-        .collect();
+    let entries = table.storage.list(table.name)?;
 
-    Ok(values)
+    entries.into_values()
+        .map(from_value)
+        .collect()
 }
 
-pub fn find_value<F, E>(path: &Path, f: F) -> Result<E, GetError>
+pub fn find_by_index<E>(table: &Table, index: &storage::Index, index_key: &str) -> Result<E, GetError>
     where
         E: Clone,
-        E: serde::de::DeserializeOwned,
-        F: Copy,
-        F: FnOnce(&E) -> bool {
-
-    let entries: DataStore<E> = file::read_json(path)
-        .map_err(GetError::Read)?;
+        E: serde::de::DeserializeOwned {
 
-    let entry = entries.iter().find_map(|(_, val)| {
-        if f(&val) {
-            Some(val)
-        } else {
-            None
-        }
-    }).ok_or(GetError::NotFound())?;
+    let value = table.storage.lookup(table.name, index, index_key)
+        .map_err(GetError::Read)?
+        .ok_or(GetError::NotFound())?;
 
-    Ok(entry.clone())
+    from_value(value)
+        .map_err(GetError::Read)
 }
 
 
 pub enum AddError {
-    Read(file::ReadJsonError),
-    Write(file::WriteJsonError),
+    Read(storage::Error),
+    Write(storage::Error),
 }
 
 impl fmt::Display for AddError {
@@ -107,25 +117,97 @@ impl fmt::Display for AddError {
 }
 
 
-pub fn add_entry<E>(path: &Path, key: &str, entry: &E) -> Result<(), AddError>
+pub fn add_entry<E>(table: &Table, key: &str, entry: &E) -> Result<(), AddError>
     where
         E: Clone,
         E: serde::Serialize,
         E: serde::de::DeserializeOwned {
 
-    let mut entries: DataStore<E> = file::read_json(path)
-        .map_err(AddError::Read)?;
+    put_entry(table, key, entry)
+        .map_err(AddError::Write)
+}
+
+// Inserts or replaces all entries in one write
+pub fn add_entries<E>(table: &Table, entries: &[(String, E)]) -> Result<(), AddError>
+    where
+        E: serde::Serialize {
+
+    let values = to_values(entries)
+        .map_err(AddError::Write)?;
+
+    table.storage.put(table.name, values)
+        .map_err(AddError::Write)
+}
+
+// Replaces all entries of the table with the given entries in one write
+pub fn replace_entries<E>(table: &Table, entries: &[(String, E)]) -> Result<(), AddError>
+    where
+        E: serde::Serialize {
 
-    entries.insert(key.to_string(), entry.clone());
+    let values = to_values(entries)
+        .map_err(AddError::Write)?;
 
-    file::write_json(path, &entries)
+    table.storage.replace(table.name, values)
         .map_err(AddError::Write)
 }
 
+pub fn upsert_entry<F, E>(table: &Table, key: &str, update_fn: F) -> Result<E, AddError>
+    where
+        E: Clone,
+        E: serde::Serialize,
+        E: serde::de::DeserializeOwned,
+        F: FnOnce(Option<&E>) -> E {
+
+    let old_entry = table.storage.get(table.name, key)
+        .map_err(AddError::Read)?
+        .map(from_value)
+        .transpose()
+        .map_err(AddError::Read)?;
+
+    let new_entry = update_fn(old_entry.as_ref());
+
+    put_entry(table, key, &new_entry)
+        .map_err(AddError::Write)?;
+
+    Ok(new_entry)
+}
+
+// Applies update_fn to every entry and sets the schema version, entries where update_fn returns None are left as is
+pub fn migrate_all<F, E>(table: &Table, version: u32, update_fn: F) -> Result<usize, AddError>
+    where
+        E: serde::Serialize,
+        E: serde::de::DeserializeOwned,
+        F: Fn(&E) -> Option<E> {
+
+    let entries = table.storage.list(table.name)
+        .map_err(AddError::Read)?;
+
+    let mut updated_entries = HashMap::new();
+
+    for (key, value) in entries {
+        let entry: E = from_value(value)
+            .map_err(AddError::Read)?;
+
+        if let Some(new_entry) = update_fn(&entry) {
+            let new_value = to_value(&new_entry)
+                .map_err(AddError::Write)?;
+
+            updated_entries.insert(key, new_value);
+        }
+    }
+
+    let updated = updated_entries.len();
+
+    table.storage.migrate(table.name, updated_entries, version)
+        .map_err(AddError::Write)?;
+
+    Ok(updated)
+}
+
 pub enum UpdateError {
-    Read(file::ReadJsonError),
+    Read(storage::Error),
     NotFound(),
-    Write(file::WriteJsonError),
+    Write(storage::Error),
 }
 
 impl fmt::Display for UpdateError {
@@ -147,37 +229,55 @@ impl fmt::Display for UpdateError {
 }
 
 
-pub fn update_entry<F, E>(path: &Path, key: &str, update_fn: F) -> Result<E, UpdateError>
+pub fn update_entry<F, E>(table: &Table, key: &str, update_fn: F) -> Result<E, UpdateError>
     where
         E: Clone,
         E: serde::Serialize,
         E: serde::de::DeserializeOwned,
         F: FnOnce(&E) -> E {
 
-    let mut entries: DataStore<E> = file::read_json(path)
+    let old_value = table.storage.get(table.name, key)
+        .map_err(UpdateError::Read)?
+        .ok_or(UpdateError::NotFound())?;
+
+    let old_entry = from_value(old_value)
         .map_err(UpdateError::Read)?;
 
-    let old_entry = entries.get(key).ok_or(UpdateError::NotFound())?;
     let new_entry = update_fn(&old_entry);
 
-    entries.insert(key.to_string(), new_entry.clone());
-
-    file::write_json(path, &entries)
+    put_entry(table, key, &new_entry)
         .map_err(UpdateError::Write)?;
 
     Ok(new_entry)
 }
 
-pub fn remove_entry<E>(path: &Path, key: &str) -> Result<(), AddError>
-    where
-        E: serde::Serialize,
-        E: serde::de::DeserializeOwned {
+pub fn remove_entry(table: &Table, key: &str) -> Result<(), AddError> {
+    table.storage.remove(table.name, key)
+        .map_err(AddError::Write)
+}
 
-    let mut entries: DataStore<E> = file::read_json(path)
-        .map_err(AddError::Read)?;
 
-    entries.remove(key);
+fn put_entry<E: serde::Serialize>(table: &Table, key: &str, entry: &E) -> Result<(), storage::Error> {
+    let value = to_value(entry)?;
+    let mut entries = HashMap::new();
 
-    file::write_json(path, &entries)
-        .map_err(AddError::Write)
+    entries.insert(key.to_string(), value);
+
+    table.storage.put(table.name, entries)
+}
+
+fn to_value<E: serde::Serialize>(entry: &E) -> Result<serde_json::Value, storage::Error> {
+    serde_json::to_value(entry)
+        .map_err(storage::Error::Serialize)
+}
+
+fn to_values<E: serde::Serialize>(entries: &[(String, E)]) -> Result<storage::Entries, storage::Error> {
+    entries.iter()
+        .map(|(key, entry)| Ok((key.clone(), to_value(entry)?)))
+        .collect()
+}
+
+fn from_value<E: serde::de::DeserializeOwned>(value: serde_json::Value) -> Result<E, storage::Error> {
+    serde_json::from_value(value)
+        .map_err(storage::Error::Deserialize)
 }
@@ -27,6 +27,17 @@ pub fn lookup<T>(environment: &Environment, key: &'static str) -> Result<T, Erro
         })
 }
 
+pub fn lookup_optional<T>(environment: &Environment, key: &'static str) -> Result<Option<T>, Error>
+    where T: FromStr,
+          T::Err: fmt::Display {
+
+    match lookup(environment, key) {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::KeyNotFound(_)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
 
 #[derive(Debug)]
 pub enum Error {
@@ -0,0 +1,385 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::fmt;
+use std::time;
+
+use crate::glot_run::config;
+use crate::glot_run::user;
+use crate::glot_run::language;
+use crate::glot_run::token;
+use crate::glot_run::util;
+
+
+// Version of the document format, schema versions of the tables are included separately
+pub const FORMAT_VERSION: u32 = 1;
+
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Document {
+    pub format_version: u32,
+    pub exported: String,
+    pub schema_versions: BTreeMap<String, u32>,
+    // Imported token hashes only work when the token hash key is the same as where they were exported from
+    pub token_hash_key_fingerprint: String,
+    pub users: Vec<user::User>,
+    pub languages: Vec<language::Language>,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportMode {
+    // Adds new entries, existing entries with different content are reported as conflicts and left as is
+    Merge,
+    // Replaces all entries with the imported entries
+    Replace,
+}
+
+impl ImportMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportMode::Merge => "merge",
+            ImportMode::Replace => "replace",
+        }
+    }
+}
+
+impl FromStr for ImportMode {
+    type Err = ParseImportModeError;
+
+    fn from_str(s: &str) -> Result<ImportMode, ParseImportModeError> {
+        match s {
+            "merge" => Ok(ImportMode::Merge),
+            "replace" => Ok(ImportMode::Replace),
+            _ => Err(ParseImportModeError(s.to_string())),
+        }
+    }
+}
+
+pub struct ParseImportModeError(String);
+
+impl fmt::Display for ParseImportModeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid import mode «{}», expected merge or replace", self.0)
+    }
+}
+
+
+#[derive(Debug, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub users: TableReport,
+    pub languages: TableReport,
+    pub conflicts: Vec<Conflict>,
+}
+
+
+#[derive(Debug, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableReport {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    pub skipped: usize,
+}
+
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Conflict {
+    pub table: String,
+    pub id: String,
+    pub reason: String,
+}
+
+
+// The entries a table should be written with, the whole table is replaced in replace mode
+pub struct TableImport<E> {
+    pub entries: Vec<(String, E)>,
+    pub report: TableReport,
+}
+
+
+pub fn new(users: Vec<user::User>, languages: Vec<language::Language>, schema_versions: BTreeMap<String, u32>, token_hash_key: &ascii::AsciiString) -> Document {
+    Document{
+        format_version: FORMAT_VERSION,
+        exported: util::rfc3339(time::SystemTime::now()),
+        schema_versions,
+        token_hash_key_fingerprint: token::key_fingerprint(token_hash_key),
+        users,
+        languages,
+    }
+}
+
+pub fn import_users(existing: &[user::User], imported: &[user::User], mode: ImportMode, conflicts: &mut Vec<Conflict>) -> TableImport<user::User> {
+    // Token hashes must be unique across users for token lookup to work.
+    // Existing users are removed in replace mode, so only the imported users can conflict then
+    let token_owners = match mode {
+        ImportMode::Merge => {
+            existing.iter()
+                .flat_map(|user| user.tokens.iter().map(move |token| (token.hash.as_str(), user.id)))
+                .collect::<HashMap<&str, uuid::Uuid>>()
+        }
+
+        ImportMode::Replace => {
+            HashMap::new()
+        }
+    };
+
+    let imported_token_owners = first_token_owners(imported);
+
+    import_table(config::USERS_TABLE, existing, imported, mode, conflicts, |user| user.id.to_string(), |user| {
+        let existing_owner = user.tokens.iter()
+            .filter_map(|token| token_owners.get(token.hash.as_str()))
+            .find(|owner_id| **owner_id != user.id);
+
+        let imported_owner = user.tokens.iter()
+            .filter_map(|token| imported_token_owners.get(token.hash.as_str()))
+            .find(|owner_id| **owner_id != user.id);
+
+        match (existing_owner, imported_owner) {
+            (Some(owner_id), _) => {
+                Some(format!("A token of the user is already used by user {}", owner_id))
+            }
+
+            (None, Some(owner_id)) => {
+                Some(format!("A token of the user is also used by the imported user {}", owner_id))
+            }
+
+            (None, None) => {
+                None
+            }
+        }
+    })
+}
+
+// Maps each token hash to the first imported user that has it
+fn first_token_owners(users: &[user::User]) -> HashMap<&str, uuid::Uuid> {
+    let mut owners = HashMap::new();
+
+    for user in users {
+        for token in &user.tokens {
+            owners.entry(token.hash.as_str()).or_insert(user.id);
+        }
+    }
+
+    owners
+}
+
+pub fn import_languages(existing: &[language::Language], imported: &[language::Language], mode: ImportMode, conflicts: &mut Vec<Conflict>) -> TableImport<language::Language> {
+    import_table(config::LANGUAGES_TABLE, existing, imported, mode, conflicts, |language| language.id.clone(), |_| None)
+}
+
+fn import_table<E, K, C>(table: &str, existing: &[E], imported: &[E], mode: ImportMode, conflicts: &mut Vec<Conflict>, key_fn: K, conflict_fn: C) -> TableImport<E>
+    where
+        E: Clone,
+        E: serde::Serialize,
+        K: Fn(&E) -> String,
+        C: Fn(&E) -> Option<String> {
+
+    let existing_entries = existing.iter()
+        .map(|entry| (key_fn(entry), entry))
+        .collect::<HashMap<String, &E>>();
+
+    let mut report = TableReport::default();
+    let mut entries = Vec::new();
+    let mut seen_keys = HashSet::new();
+
+    for entry in imported {
+        let key = key_fn(entry);
+
+        if !seen_keys.insert(key.clone()) {
+            report.skipped += 1;
+            conflicts.push(new_conflict(table, &key, "The id is used more than once in the import"));
+            continue
+        }
+
+        if let Some(reason) = conflict_fn(entry) {
+            report.skipped += 1;
+            conflicts.push(new_conflict(table, &key, &reason));
+            continue
+        }
+
+        match existing_entries.get(&key) {
+            Some(existing_entry) if is_equal(*existing_entry, entry) => {
+                report.unchanged += 1;
+            }
+
+            Some(_) if mode == ImportMode::Merge => {
+                report.skipped += 1;
+                conflicts.push(new_conflict(table, &key, "An entry with the same id and different content exists"));
+                continue
+            }
+
+            Some(_) => {
+                report.updated += 1;
+            }
+
+            None => {
+                report.created += 1;
+            }
+        }
+
+        entries.push((key, entry.clone()));
+    }
+
+    if mode == ImportMode::Replace {
+        report.removed = existing_entries.keys()
+            .filter(|key| !seen_keys.contains(*key))
+            .count();
+    }
+
+    TableImport{
+        entries,
+        report,
+    }
+}
+
+fn is_equal<E: serde::Serialize>(a: &E, b: &E) -> bool {
+    match (serde_json::to_value(a), serde_json::to_value(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn new_conflict(table: &str, id: &str, reason: &str) -> Conflict {
+    Conflict{
+        table: table.to_string(),
+        id: id.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(token_hashes: &[&str]) -> user::User {
+        let tokens = token_hashes.iter()
+            .map(|hash| user::new_token(user::DEFAULT_TOKEN_NAME, hash.to_string(), None))
+            .collect();
+
+        user::test_user(tokens)
+    }
+
+    fn language(name: &str) -> language::Language {
+        language::test_language(name, "latest", &[])
+    }
+
+    fn changed(language: &language::Language) -> language::Language {
+        language::Language{
+            image: format!("{}-changed", language.image),
+            ..language.clone()
+        }
+    }
+
+    fn keys<E>(table_import: &TableImport<E>) -> Vec<&str> {
+        table_import.entries.iter().map(|(key, _)| key.as_str()).collect()
+    }
+
+    fn conflict_ids(conflicts: &[Conflict]) -> Vec<&str> {
+        conflicts.iter().map(|conflict| conflict.id.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_import_mode() {
+        assert_eq!(ImportMode::from_str("merge").ok(), Some(ImportMode::Merge));
+        assert_eq!(ImportMode::from_str("replace").ok(), Some(ImportMode::Replace));
+        assert!(ImportMode::from_str("append").is_err());
+    }
+
+    #[test]
+    fn merge_skips_changed_entries() {
+        let python = language("python");
+        let rust = language("rust");
+        let go = language("go");
+
+        let mut conflicts = Vec::new();
+        let result = import_languages(&[python.clone(), rust.clone()], &[python.clone(), changed(&rust), go.clone()], ImportMode::Merge, &mut conflicts);
+
+        assert_eq!(result.report.created, 1);
+        assert_eq!(result.report.unchanged, 1);
+        assert_eq!(result.report.updated, 0);
+        assert_eq!(result.report.skipped, 1);
+        assert_eq!(result.report.removed, 0);
+        assert_eq!(keys(&result), vec![python.id.as_str(), go.id.as_str()]);
+        assert_eq!(conflict_ids(&conflicts), vec![rust.id.as_str()]);
+    }
+
+    #[test]
+    fn replace_updates_changed_entries_and_removes_missing_entries() {
+        let python = language("python");
+        let rust = language("rust");
+        let go = language("go");
+
+        let mut conflicts = Vec::new();
+        let result = import_languages(&[python.clone(), rust.clone()], &[changed(&rust), go.clone()], ImportMode::Replace, &mut conflicts);
+
+        assert_eq!(result.report.created, 1);
+        assert_eq!(result.report.updated, 1);
+        assert_eq!(result.report.removed, 1);
+        assert_eq!(result.report.skipped, 0);
+        assert_eq!(keys(&result), vec![rust.id.as_str(), go.id.as_str()]);
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn skips_ids_used_more_than_once() {
+        let rust = language("rust");
+
+        let mut conflicts = Vec::new();
+        let result = import_languages(&[], &[rust.clone(), changed(&rust)], ImportMode::Replace, &mut conflicts);
+
+        assert_eq!(result.report.created, 1);
+        assert_eq!(result.report.skipped, 1);
+        assert_eq!(result.entries[0].1.image, rust.image);
+        assert_eq!(conflict_ids(&conflicts), vec![rust.id.as_str()]);
+    }
+
+    #[test]
+    fn merge_skips_users_with_tokens_of_existing_users() {
+        let existing = user(&["a"]);
+        let imported = user(&["b", "a"]);
+
+        let mut conflicts = Vec::new();
+        let result = import_users(std::slice::from_ref(&existing), &[existing.clone(), imported.clone()], ImportMode::Merge, &mut conflicts);
+
+        assert_eq!(result.report.unchanged, 1);
+        assert_eq!(result.report.skipped, 1);
+        assert_eq!(conflict_ids(&conflicts), vec![imported.id.to_string()]);
+    }
+
+    #[test]
+    fn replace_ignores_tokens_of_removed_users() {
+        let existing = user(&["a"]);
+        let imported = user(&["a"]);
+
+        let mut conflicts = Vec::new();
+        let result = import_users(&[existing], std::slice::from_ref(&imported), ImportMode::Replace, &mut conflicts);
+
+        assert_eq!(result.report.created, 1);
+        assert_eq!(result.report.removed, 1);
+        assert_eq!(keys(&result), vec![imported.id.to_string()]);
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn skips_imported_users_sharing_a_token() {
+        for mode in &[ImportMode::Merge, ImportMode::Replace] {
+            let first = user(&["a"]);
+            let second = user(&["b", "a"]);
+
+            let mut conflicts = Vec::new();
+            let result = import_users(&[], &[first.clone(), second.clone()], *mode, &mut conflicts);
+
+            assert_eq!(result.report.created, 1);
+            assert_eq!(result.report.skipped, 1);
+            assert_eq!(keys(&result), vec![first.id.to_string()]);
+            assert_eq!(conflict_ids(&conflicts), vec![second.id.to_string()]);
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use std::io;
+use std::io::BufRead;
+use std::io::Write;
 use std::fmt;
 use std::path;
 use std::fs::File;
+use std::fs::OpenOptions;
 use tempfile::NamedTempFile;
 
 
@@ -15,10 +18,79 @@ pub fn write_json<T: serde::Serialize>(path: &path::Path, value: &T) -> Result<(
     serde_json::to_writer_pretty(&file, value)
         .map_err(WriteJsonError::Serialize)?;
 
+    persist(file, path, dir)
+}
+
+// Writes json lines to a temp file that replaces path when persisted, so the lines don't need to be in memory at once
+pub struct JsonLinesWriter {
+    file: io::BufWriter<NamedTempFile>,
+    path: path::PathBuf,
+}
+
+impl JsonLinesWriter {
+    pub fn new(path: &path::Path) -> Result<JsonLinesWriter, WriteJsonError> {
+        let dir = path.parent()
+            .ok_or(WriteJsonError::DetermineDir())?;
+
+        let file = NamedTempFile::new_in(dir)
+            .map_err(WriteJsonError::CreateTempFile)?;
+
+        Ok(JsonLinesWriter{
+            file: io::BufWriter::new(file),
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn write<T: serde::Serialize>(&mut self, value: &T) -> Result<(), WriteJsonError> {
+        serde_json::to_writer(&mut self.file, value)
+            .map_err(WriteJsonError::Serialize)?;
+
+        self.file.write_all(b"\n")
+            .map_err(WriteJsonError::Write)
+    }
+
+    pub fn persist(self) -> Result<(), WriteJsonError> {
+        let file = self.file.into_inner()
+            .map_err(|err| WriteJsonError::Write(err.into_error()))?;
+
+        let dir = self.path.parent()
+            .ok_or(WriteJsonError::DetermineDir())?;
+
+        persist(file, &self.path, dir)
+    }
+}
+
+// The temp file is synced before it replaces path and the directory is synced after, so the new file survives a crash
+fn persist(file: NamedTempFile, path: &path::Path, dir: &path::Path) -> Result<(), WriteJsonError> {
+    file.as_file().sync_all()
+        .map_err(WriteJsonError::Sync)?;
+
     file.persist(path)
         .map_err(|err| WriteJsonError::Persist(err.error))?;
 
-    Ok(())
+    sync_dir(dir)
+        .map_err(WriteJsonError::Sync)
+}
+
+pub fn sync_dir(dir: &path::Path) -> Result<(), io::Error> {
+    File::open(dir)?.sync_all()
+}
+
+// Replaces path with a copy of source with the same guarantees as write_json
+pub fn copy_durable(source: &path::Path, path: &path::Path) -> Result<(), io::Error> {
+    let dir = path.parent()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid file path"))?;
+
+    let mut file = NamedTempFile::new_in(dir)?;
+    let mut source_file = File::open(source)?;
+
+    io::copy(&mut source_file, &mut file)?;
+    file.as_file().sync_all()?;
+
+    file.persist(path)
+        .map_err(|err| err.error)?;
+
+    sync_dir(dir)
 }
 
 pub fn read_json<T: serde::de::DeserializeOwned>(path: &path::Path) -> Result<T, ReadJsonError> {
@@ -31,12 +103,62 @@ pub fn read_json<T: serde::de::DeserializeOwned>(path: &path::Path) -> Result<T,
         .map_err(ReadJsonError::Deserialize)
 }
 
+pub fn append_json_line<T: serde::Serialize>(path: &path::Path, value: &T) -> Result<(), AppendJsonError> {
+    let mut line = serde_json::to_vec(value)
+        .map_err(AppendJsonError::Serialize)?;
+
+    line.push(b'\n');
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(AppendJsonError::Open)?;
+
+    // The line is written with a single write call so concurrent appends don't interleave
+    file.write_all(&line)
+        .map_err(AppendJsonError::Write)
+}
+
+// Reads one line at a time so the whole file is never in memory, stops when f returns false
+pub fn for_each_json_line<T, F>(path: &path::Path, mut f: F) -> Result<(), ReadJsonError>
+    where
+        T: serde::de::DeserializeOwned,
+        F: FnMut(T) -> bool {
+
+    if !path.exists() {
+        return Ok(())
+    }
+
+    let file = File::open(path)
+        .map_err(ReadJsonError::Open)?;
+
+    let reader = io::BufReader::new(file);
+
+    for line in reader.lines() {
+        let line = line.map_err(ReadJsonError::Read)?;
+
+        if !line.trim().is_empty() {
+            let value = serde_json::from_str(&line)
+                .map_err(ReadJsonError::Deserialize)?;
+
+            if !f(value) {
+                break
+            }
+        }
+    }
+
+    Ok(())
+}
+
 
 
 pub enum WriteJsonError {
     DetermineDir(),
     CreateTempFile(io::Error),
     Serialize(serde_json::Error),
+    Write(io::Error),
+    Sync(io::Error),
     Persist(io::Error),
 }
 
@@ -52,6 +174,12 @@ impl fmt::Display for WriteJsonError {
             WriteJsonError::Serialize(err) =>
                 write!(f, "Failed to serialize config: {}", err),
 
+            WriteJsonError::Write(err) =>
+                write!(f, "Failed to write temp file: {}", err),
+
+            WriteJsonError::Sync(err) =>
+                write!(f, "Failed to sync file: {}", err),
+
             WriteJsonError::Persist(err) =>
                 write!(f, "Failed to persist file: {}", err),
         }
@@ -60,8 +188,32 @@ impl fmt::Display for WriteJsonError {
 
 
 
+pub enum AppendJsonError {
+    Serialize(serde_json::Error),
+    Open(io::Error),
+    Write(io::Error),
+}
+
+impl fmt::Display for AppendJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppendJsonError::Serialize(err) =>
+                write!(f, "Failed to serialize: {}", err),
+
+            AppendJsonError::Open(err) =>
+                write!(f, "Failed to open file: {}", err),
+
+            AppendJsonError::Write(err) =>
+                write!(f, "Failed to write to file: {}", err),
+        }
+    }
+}
+
+
+
 pub enum ReadJsonError {
     Open(io::Error),
+    Read(io::Error),
     Deserialize(serde_json::Error),
 }
 
@@ -71,6 +223,9 @@ impl fmt::Display for ReadJsonError {
             ReadJsonError::Open(err) =>
                 write!(f, "Failed to open file: {}", err),
 
+            ReadJsonError::Read(err) =>
+                write!(f, "Failed to read file: {}", err),
+
             ReadJsonError::Deserialize(err) =>
                 write!(f, "Failed to deserialize: {}", err),
         }
@@ -1,3 +1,9 @@
+use std::time;
+use std::fmt;
+
+use crate::glot_run::util;
+use crate::glot_run::migration;
+use crate::glot_run::storage;
 
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -6,6 +12,41 @@ pub struct Language {
     pub name: String,
     pub version: String,
     pub image: String,
+    // Incremented on every change made through the admin api
+    pub revision: u64,
+    pub metadata: Metadata,
+    // Alternative versions that resolve to this language, i.e. latest or stable
+    pub aliases: Vec<String>,
+    pub state: State,
+    // When a deprecated language is planned to be disabled
+    pub sunset: Option<String>,
+    pub created: String,
+    pub modified: String,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+    Active,
+    // Runs are allowed, but clients are told to move to another version
+    Deprecated,
+    // Listed, but runs are rejected
+    Disabled,
+}
+
+
+// Information for clients, i.e. to set up an editor for the language
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metadata {
+    pub display_name: Option<String>,
+    pub file_name: Option<String>,
+    pub file_extension: Option<String>,
+    pub run_command: Option<String>,
+    pub editor_mode: Option<String>,
+    // Source of an example program, i.e. hello world
+    pub example: Option<String>,
 }
 
 
@@ -14,20 +55,359 @@ pub struct LanguageData {
     pub name: String,
     pub version: String,
     pub image: String,
+    #[serde(default)]
+    pub metadata: Metadata,
+    #[serde(default)]
+    pub aliases: Vec<String>,
 }
 
+
+// The name and version can't be changed since the id is derived from them
+#[derive(Debug, serde::Deserialize)]
+pub struct UpdateData {
+    pub image: Option<String>,
+    pub metadata: Option<Metadata>,
+    pub aliases: Option<Vec<String>>,
+    pub state: Option<State>,
+    #[serde(default, deserialize_with = "util::deserialize_nullable")]
+    pub sunset: Option<Option<String>>,
+}
+
+// Index of languages by name and alias, the key is name/alias
+pub const ALIAS_INDEX: storage::Index = storage::Index{
+    name: "alias",
+    keys: alias_keys,
+};
+
+
 pub fn new(data: &LanguageData) -> Language {
-    let id = sha1_hash(&format!("{}{}", data.name, data.version));
+    let id = id(&data.name, &data.version);
+    let now = time::SystemTime::now();
 
     Language{
         id,
         name: data.name.clone(),
         version: data.version.clone(),
         image: data.image.clone(),
+        revision: 1,
+        metadata: data.metadata.clone(),
+        aliases: data.aliases.clone(),
+        state: State::Active,
+        sunset: None,
+        created: util::rfc3339(now),
+        modified: util::rfc3339(now),
+    }
+}
+
+// Replaces an existing language with the same name and version, the state is only changed by update
+pub fn replace(language: &Language, data: &LanguageData) -> Language {
+    let now = time::SystemTime::now();
+
+    Language{
+        image: data.image.clone(),
+        metadata: data.metadata.clone(),
+        aliases: data.aliases.clone(),
+        revision: language.revision + 1,
+        modified: util::rfc3339(now),
+        ..language.clone()
+    }
+}
+
+pub fn update(language: &Language, data: &UpdateData) -> Language {
+    let now = time::SystemTime::now();
+
+    Language{
+        image: data.image.clone().unwrap_or_else(|| language.image.clone()),
+        metadata: data.metadata.clone().unwrap_or_else(|| language.metadata.clone()),
+        aliases: data.aliases.clone().unwrap_or_else(|| language.aliases.clone()),
+        state: data.state.unwrap_or(language.state),
+        sunset: data.sunset.clone().unwrap_or_else(|| language.sunset.clone()),
+        revision: language.revision + 1,
+        modified: util::rfc3339(now),
+        ..language.clone()
+    }
+}
+
+pub fn id(name: &str, version: &str) -> String {
+    sha1_hash(&format!("{}{}", name, version))
+}
+
+pub fn alias_key(name: &str, alias: &str) -> String {
+    format!("{}/{}", name, alias)
+}
+
+fn alias_keys(entry: &serde_json::Value) -> Vec<String> {
+    let name = entry.get("name").and_then(|name| name.as_str());
+    let aliases = entry.get("aliases").and_then(|aliases| aliases.as_array());
+
+    match (name, aliases) {
+        (Some(name), Some(aliases)) => {
+            aliases.iter()
+                .filter_map(|alias| alias.as_str())
+                .map(|alias| alias_key(name, alias))
+                .collect()
+        }
+
+        _ => {
+            vec![]
+        }
+    }
+}
+
+// An alias must not be empty and must not be a version of the language, since exact versions take precedence over aliases
+pub fn validate_aliases(languages: &[Language], language: &Language) -> Result<(), AliasError> {
+    for alias in &language.aliases {
+        if alias.trim().is_empty() {
+            return Err(AliasError::Empty())
+        }
+
+        if alias == &language.version {
+            return Err(AliasError::OwnVersion(alias.clone()))
+        }
+
+        let is_version = languages.iter()
+            .any(|other| other.name == language.name && other.id != language.id && &other.version == alias);
+
+        if is_version {
+            return Err(AliasError::OtherVersion(alias.clone()))
+        }
+    }
+
+    Ok(())
+}
+
+// An alias points to one version, returns the other versions of the language that need to give up its aliases
+pub fn take_aliases(languages: &[Language], language: &Language) -> Vec<Language> {
+    let now = time::SystemTime::now();
+
+    languages.iter()
+        .filter(|other| other.name == language.name && other.id != language.id)
+        .filter(|other| other.aliases.iter().any(|alias| language.aliases.contains(alias)))
+        .map(|other| {
+            let aliases = other.aliases.iter()
+                .filter(|alias| !language.aliases.contains(alias))
+                .cloned()
+                .collect();
+
+            Language{
+                aliases,
+                revision: other.revision + 1,
+                modified: util::rfc3339(now),
+                ..other.clone()
+            }
+        })
+        .collect()
+}
+
+pub fn migrations() -> Vec<migration::Migration> {
+    vec![
+        migration::Migration{
+            version: 1,
+            description: "Add revision",
+            migrate: Box::new(migration::add_revision),
+        },
+        migration::Migration{
+            version: 2,
+            description: "Add created and modified timestamps",
+            migrate: Box::new(migrate_timestamps),
+        },
+        migration::Migration{
+            version: 3,
+            description: "Add metadata",
+            migrate: Box::new(migrate_metadata),
+        },
+        migration::Migration{
+            version: 4,
+            description: "Add aliases",
+            migrate: Box::new(migrate_aliases),
+        },
+        migration::Migration{
+            version: 5,
+            description: "Add state and sunset",
+            migrate: Box::new(migrate_state),
+        },
+    ]
+}
+
+// The time of creation is unknown for languages created before they had timestamps, the migration time is used instead
+fn migrate_timestamps(entry: &serde_json::Value) -> Option<serde_json::Value> {
+    if entry.get("created").is_some() {
+        return None
     }
+
+    let now = util::rfc3339(time::SystemTime::now());
+    let mut new_entry = entry.clone();
+    let fields = new_entry.as_object_mut()?;
+
+    fields.insert("created".to_string(), serde_json::json!(now));
+    fields.insert("modified".to_string(), serde_json::json!(now));
+
+    Some(new_entry)
+}
+
+// Languages created before they had metadata get empty metadata
+fn migrate_metadata(entry: &serde_json::Value) -> Option<serde_json::Value> {
+    if entry.get("metadata").is_some() {
+        return None
+    }
+
+    let mut new_entry = entry.clone();
+    new_entry.as_object_mut()?.insert("metadata".to_string(), serde_json::json!({}));
+
+    Some(new_entry)
+}
+
+fn migrate_aliases(entry: &serde_json::Value) -> Option<serde_json::Value> {
+    if entry.get("aliases").is_some() {
+        return None
+    }
+
+    let mut new_entry = entry.clone();
+    new_entry.as_object_mut()?.insert("aliases".to_string(), serde_json::json!([]));
+
+    Some(new_entry)
+}
+
+fn migrate_state(entry: &serde_json::Value) -> Option<serde_json::Value> {
+    if entry.get("state").is_some() {
+        return None
+    }
+
+    let mut new_entry = entry.clone();
+    let fields = new_entry.as_object_mut()?;
+
+    fields.insert("state".to_string(), serde_json::json!(State::Active));
+    fields.insert("sunset".to_string(), serde_json::Value::Null);
+
+    Some(new_entry)
 }
 
 
 fn sha1_hash(s: &str) -> String {
     sha1::Sha1::from(s).hexdigest()
 }
+
+
+pub enum AliasError {
+    Empty(),
+    OwnVersion(String),
+    OtherVersion(String),
+}
+
+impl fmt::Display for AliasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AliasError::Empty() => {
+                write!(f, "Aliases can't be empty")
+            }
+
+            AliasError::OwnVersion(alias) => {
+                write!(f, "The alias «{}» is the version of the language", alias)
+            }
+
+            AliasError::OtherVersion(alias) => {
+                write!(f, "The alias «{}» is another version of the language", alias)
+            }
+        }
+    }
+}
+
+
+// A language with the given aliases and no metadata, for tests
+#[cfg(test)]
+pub(crate) fn test_language(name: &str, version: &str, aliases: &[&str]) -> Language {
+    new(&LanguageData{
+        name: name.to_string(),
+        version: version.to_string(),
+        image: format!("glot/{}:{}", name, version),
+        metadata: Metadata::default(),
+        aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alias_keys_include_name() {
+        let python = test_language("python", "3.9", &["latest", "stable"]);
+        let entry = serde_json::to_value(&python).unwrap();
+
+        assert_eq!(alias_keys(&entry), vec!["python/latest", "python/stable"]);
+        assert!(alias_keys(&serde_json::json!({"name": "python"})).is_empty());
+    }
+
+    #[test]
+    fn id_is_derived_from_name_and_version() {
+        let python = test_language("python", "3.9", &[]);
+
+        assert_eq!(python.id, id("python", "3.9"));
+        assert_ne!(python.id, id("python", "3.8"));
+    }
+
+    #[test]
+    fn validate_aliases_accepts_new_aliases() {
+        let existing = test_language("python", "3.8", &["stable"]);
+        let python = test_language("python", "3.9", &["latest", "stable"]);
+
+        assert!(validate_aliases(&[existing], &python).is_ok());
+    }
+
+    #[test]
+    fn validate_aliases_rejects_empty_alias() {
+        let python = test_language("python", "3.9", &[" "]);
+
+        assert!(matches!(validate_aliases(&[], &python), Err(AliasError::Empty())));
+    }
+
+    #[test]
+    fn validate_aliases_rejects_own_version() {
+        let python = test_language("python", "3.9", &["3.9"]);
+
+        assert!(matches!(validate_aliases(&[], &python), Err(AliasError::OwnVersion(alias)) if alias == "3.9"));
+    }
+
+    #[test]
+    fn validate_aliases_rejects_other_version() {
+        let languages = vec![test_language("python", "3.8", &[]), test_language("ruby", "3.7", &[])];
+
+        let python = test_language("python", "3.9", &["3.8"]);
+        assert!(matches!(validate_aliases(&languages, &python), Err(AliasError::OtherVersion(alias)) if alias == "3.8"));
+
+        // Versions of other languages are fine
+        let python = test_language("python", "3.9", &["3.7"]);
+        assert!(validate_aliases(&languages, &python).is_ok());
+    }
+
+    #[test]
+    fn validate_aliases_ignores_the_language_being_replaced() {
+        let existing = test_language("python", "3.9", &[]);
+        let python = test_language("python", "3.9", &["latest"]);
+
+        assert!(validate_aliases(&[existing], &python).is_ok());
+    }
+
+    #[test]
+    fn take_aliases_moves_aliases_to_language() {
+        let old = test_language("python", "3.8", &["latest", "stable"]);
+        let other_language = test_language("ruby", "3.0", &["latest"]);
+        let python = test_language("python", "3.9", &["latest"]);
+
+        let changed = take_aliases(&[old.clone(), other_language, python.clone()], &python);
+
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].id, old.id);
+        assert_eq!(changed[0].aliases, vec!["stable".to_string()]);
+        assert_eq!(changed[0].revision, old.revision + 1);
+    }
+
+    #[test]
+    fn take_aliases_leaves_languages_without_shared_aliases() {
+        let old = test_language("python", "3.8", &["stable"]);
+        let python = test_language("python", "3.9", &["latest"]);
+
+        assert!(take_aliases(&[old], &python).is_empty());
+    }
+}
@@ -0,0 +1,270 @@
+use std::fmt;
+
+use crate::glot_run::datastore;
+
+
+
+// Returns None when the entry is already up to date
+pub type MigrateFn = Box<dyn Fn(&serde_json::Value) -> Option<serde_json::Value>>;
+
+
+pub struct Migration {
+    // The schema version of the table after the migration
+    pub version: u32,
+    pub description: &'static str,
+    pub migrate: MigrateFn,
+}
+
+
+#[derive(Debug)]
+pub struct Report {
+    // False when a dry run finds a table that would be created
+    pub exists: bool,
+    pub from_version: u32,
+    pub to_version: u32,
+    // Version and description of the migrations that were applied, or would be applied in a dry run
+    pub migrations: Vec<(u32, &'static str)>,
+    pub updated: usize,
+}
+
+
+pub enum Error {
+    Datastore(datastore::AddError),
+    // The table was written by a newer version, the schema is unknown to this version
+    UnknownVersion{table: &'static str, version: u32, latest_version: u32},
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Datastore(err) => {
+                write!(f, "{}", err)
+            }
+
+            Error::UnknownVersion{table, version, latest_version} => {
+                write!(f, "The schema version of {} is {}, but the latest known version is {}", table, version, latest_version)
+            }
+        }
+    }
+}
+
+
+// Applies the migrations that are newer than the schema version of the table, nothing is written when dry_run is set.
+// A missing table is treated as an empty table at version 0 in a dry run
+pub fn run(table: &datastore::Table, migrations: &[Migration], dry_run: bool) -> Result<Report, Error> {
+    let exists = datastore::exists(table)
+        .map_err(|err| Error::Datastore(datastore::AddError::Read(err)))?;
+
+    let from_version = if exists {
+        datastore::schema_version(table)
+            .map_err(|err| Error::Datastore(datastore::AddError::Read(err)))?
+    } else {
+        0
+    };
+
+    let latest_version = migrations.iter()
+        .map(|migration| migration.version)
+        .max()
+        .unwrap_or(0);
+
+    if from_version > latest_version {
+        return Err(Error::UnknownVersion{
+            table: table.name(),
+            version: from_version,
+            latest_version,
+        })
+    }
+
+    let mut pending = migrations.iter()
+        .filter(|migration| migration.version > from_version)
+        .collect::<Vec<&Migration>>();
+
+    pending.sort_by_key(|migration| migration.version);
+
+    let to_version = pending.last()
+        .map(|migration| migration.version)
+        .unwrap_or(from_version);
+
+    let updated = if pending.is_empty() || !exists {
+        0
+    } else if dry_run {
+        let entries = datastore::list_values::<serde_json::Value>(table)
+            .map_err(|err| Error::Datastore(datastore::AddError::Read(err)))?;
+
+        entries.iter()
+            .filter_map(|entry| apply(&pending, entry))
+            .count()
+    } else {
+        datastore::migrate_all(table, to_version, |entry| apply(&pending, entry))
+            .map_err(Error::Datastore)?
+    };
+
+    Ok(Report{
+        exists,
+        from_version,
+        to_version,
+        migrations: pending.iter().map(|migration| (migration.version, migration.description)).collect(),
+        updated,
+    })
+}
+
+// Returns the migrated entry if any of the migrations changed it
+fn apply(migrations: &[&Migration], entry: &serde_json::Value) -> Option<serde_json::Value> {
+    migrations.iter().fold(None, |migrated, migration| {
+        let current = migrated.as_ref().unwrap_or(entry);
+
+        (migration.migrate)(current).or(migrated)
+    })
+}
+
+// Sets the revision of entries created before users and languages had revisions
+pub fn add_revision(entry: &serde_json::Value) -> Option<serde_json::Value> {
+    if entry.get("revision").is_some() {
+        return None
+    }
+
+    let mut new_entry = entry.clone();
+    new_entry.as_object_mut()?.insert("revision".to_string(), serde_json::json!(1));
+
+    Some(new_entry)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time;
+
+    use crate::glot_run::storage;
+    use crate::glot_run::backup;
+
+    fn table(dir: &tempfile::TempDir) -> datastore::Table {
+        let storage = storage::new(storage::Backend::JsonFile, dir.path(), backup::Config{
+            dir: dir.path().join("backups"),
+            count: 0,
+            interval: time::Duration::from_secs(0),
+        }, &[]);
+
+        datastore::Table::new(&storage, "entries")
+    }
+
+    fn add_name(entry: &serde_json::Value) -> Option<serde_json::Value> {
+        if entry.get("name").is_some() {
+            return None
+        }
+
+        let mut new_entry = entry.clone();
+        new_entry.as_object_mut()?.insert("name".to_string(), serde_json::json!("unnamed"));
+
+        Some(new_entry)
+    }
+
+    fn migrations() -> Vec<Migration> {
+        // Listed out of order, migrations are applied by version
+        vec![
+            Migration{
+                version: 2,
+                description: "Add revision",
+                migrate: Box::new(add_revision),
+            },
+            Migration{
+                version: 1,
+                description: "Add name",
+                migrate: Box::new(add_name),
+            },
+        ]
+    }
+
+    fn run_ok(table: &datastore::Table, migrations: &[Migration], dry_run: bool) -> Report {
+        run(table, migrations, dry_run).ok().unwrap()
+    }
+
+    #[test]
+    fn add_revision_only_changes_entries_without_revision() {
+        assert_eq!(add_revision(&serde_json::json!({"id": "a"})), Some(serde_json::json!({"id": "a", "revision": 1})));
+        assert_eq!(add_revision(&serde_json::json!({"id": "a", "revision": 3})), None);
+    }
+
+    #[test]
+    fn apply_chains_migrations() {
+        let migrations = migrations();
+        let pending = migrations.iter().collect::<Vec<&Migration>>();
+
+        assert_eq!(apply(&pending, &serde_json::json!({})), Some(serde_json::json!({"name": "unnamed", "revision": 1})));
+        assert_eq!(apply(&pending, &serde_json::json!({"name": "a"})), Some(serde_json::json!({"name": "a", "revision": 1})));
+        assert_eq!(apply(&pending, &serde_json::json!({"name": "a", "revision": 2})), None);
+    }
+
+    #[test]
+    fn migrates_entries_and_sets_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let table = table(&dir);
+
+        datastore::init(&table).ok().unwrap();
+        datastore::add_entries(&table, &[
+            ("a".to_string(), serde_json::json!({})),
+            ("b".to_string(), serde_json::json!({"name": "b", "revision": 4})),
+        ]).ok().unwrap();
+
+        let report = run_ok(&table, &migrations(), false);
+        assert!(report.exists);
+        assert_eq!(report.from_version, 0);
+        assert_eq!(report.to_version, 2);
+        assert_eq!(report.migrations, vec![(1, "Add name"), (2, "Add revision")]);
+        assert_eq!(report.updated, 1);
+
+        assert_eq!(datastore::schema_version(&table).ok(), Some(2));
+        assert_eq!(datastore::get_entry::<serde_json::Value>(&table, "a").ok(), Some(serde_json::json!({"name": "unnamed", "revision": 1})));
+
+        let report = run_ok(&table, &migrations(), false);
+        assert_eq!(report.from_version, 2);
+        assert!(report.migrations.is_empty());
+        assert_eq!(report.updated, 0);
+    }
+
+    #[test]
+    fn dry_run_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let table = table(&dir);
+
+        datastore::init(&table).ok().unwrap();
+        datastore::add_entry(&table, "a", &serde_json::json!({})).ok().unwrap();
+
+        let report = run_ok(&table, &migrations(), true);
+        assert_eq!(report.to_version, 2);
+        assert_eq!(report.updated, 1);
+
+        assert_eq!(datastore::schema_version(&table).ok(), Some(0));
+        assert_eq!(datastore::get_entry::<serde_json::Value>(&table, "a").ok(), Some(serde_json::json!({})));
+    }
+
+    #[test]
+    fn rejects_newer_schema_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let table = table(&dir);
+
+        datastore::init(&table).ok().unwrap();
+        datastore::migrate_all(&table, 3, |_: &serde_json::Value| None).ok().unwrap();
+
+        for dry_run in [false, true] {
+            let result = run(&table, &migrations(), dry_run);
+            assert!(matches!(result, Err(Error::UnknownVersion{version: 3, latest_version: 2, ..})));
+        }
+
+        assert_eq!(datastore::schema_version(&table).ok(), Some(3));
+    }
+
+    #[test]
+    fn dry_run_does_not_create_missing_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let table = table(&dir);
+
+        let report = run_ok(&table, &migrations(), true);
+        assert!(!report.exists);
+        assert_eq!(report.from_version, 0);
+        assert_eq!(report.to_version, 2);
+        assert_eq!(report.updated, 0);
+
+        assert_eq!(datastore::exists(&table).ok(), Some(false));
+    }
+}
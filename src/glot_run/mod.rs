@@ -6,4 +6,16 @@ pub mod util;
 pub mod user;
 pub mod language;
 pub mod datastore;
+pub mod storage;
+pub mod backup;
+pub mod migration;
+pub mod export;
 pub mod run;
+pub mod rate_limit;
+pub mod concurrency_limit;
+pub mod usage;
+pub mod quota;
+pub mod token;
+pub mod credential;
+pub mod audit;
+pub mod run_log;
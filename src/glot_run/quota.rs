@@ -0,0 +1,161 @@
+use std::time;
+use std::fmt;
+
+use crate::glot_run::util;
+use crate::glot_run::usage;
+
+
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Quota {
+    pub daily: Option<Limit>,
+    pub monthly: Option<Limit>,
+}
+
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Limit {
+    pub max_runs: Option<u64>,
+    pub max_run_seconds: Option<u64>,
+}
+
+
+pub fn check(quota: &Quota, quota_usage: &usage::QuotaUsage, now: time::SystemTime) -> Result<(), Error> {
+    if let Some(limit) = &quota.daily {
+        let period_usage = usage::current_period(&quota_usage.daily, &util::date(now));
+        check_limit(limit, &period_usage, Period::Daily)?;
+    }
+
+    if let Some(limit) = &quota.monthly {
+        let period_usage = usage::current_period(&quota_usage.monthly, &util::month(now));
+        check_limit(limit, &period_usage, Period::Monthly)?;
+    }
+
+    Ok(())
+}
+
+fn check_limit(limit: &Limit, period_usage: &usage::PeriodUsage, period: Period) -> Result<(), Error> {
+    if let Some(max_runs) = limit.max_runs {
+        util::err_if_false(period_usage.runs < max_runs, Error::RunsExceeded(period, max_runs))?;
+    }
+
+    if let Some(max_run_seconds) = limit.max_run_seconds {
+        util::err_if_false(period_usage.wall_time_ms < max_run_seconds * 1000, Error::RunSecondsExceeded(period, max_run_seconds))?;
+    }
+
+    Ok(())
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub enum Period {
+    Daily,
+    Monthly,
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Period::Daily => {
+                write!(f, "Daily")
+            }
+
+            Period::Monthly => {
+                write!(f, "Monthly")
+            }
+        }
+    }
+}
+
+
+pub enum Error {
+    RunsExceeded(Period, u64),
+    RunSecondsExceeded(Period, u64),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::RunsExceeded(period, max_runs) => {
+                write!(f, "{} quota of {} runs exceeded", period, max_runs)
+            }
+
+            Error::RunSecondsExceeded(period, max_run_seconds) => {
+                write!(f, "{} quota of {} run seconds exceeded", period, max_run_seconds)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2021-03-15T00:00:00Z
+    fn now() -> time::SystemTime {
+        time::UNIX_EPOCH + time::Duration::from_secs(1_615_766_400)
+    }
+
+    fn period_usage(period: &str, runs: u64, wall_time_ms: u64) -> usage::PeriodUsage {
+        usage::PeriodUsage{
+            period: period.to_string(),
+            runs,
+            wall_time_ms,
+        }
+    }
+
+    fn quota_usage(daily: usage::PeriodUsage, monthly: usage::PeriodUsage) -> usage::QuotaUsage {
+        usage::QuotaUsage{
+            daily,
+            monthly,
+        }
+    }
+
+    fn limit(max_runs: Option<u64>, max_run_seconds: Option<u64>) -> Limit {
+        Limit{
+            max_runs,
+            max_run_seconds,
+        }
+    }
+
+    #[test]
+    fn allows_everything_without_limits() {
+        let quota = Quota{daily: None, monthly: None};
+        let quota_usage = quota_usage(period_usage("2021-03-15", 1000, 1000), period_usage("2021-03", 1000, 1000));
+
+        assert!(check(&quota, &quota_usage, now()).is_ok());
+    }
+
+    #[test]
+    fn rejects_when_daily_runs_are_used_up() {
+        let quota = Quota{daily: Some(limit(Some(10), None)), monthly: None};
+
+        let below = quota_usage(period_usage("2021-03-15", 9, 0), Default::default());
+        assert!(check(&quota, &below, now()).is_ok());
+
+        let reached = quota_usage(period_usage("2021-03-15", 10, 0), Default::default());
+        assert!(matches!(check(&quota, &reached, now()), Err(Error::RunsExceeded(Period::Daily, 10))));
+    }
+
+    #[test]
+    fn rejects_when_monthly_run_seconds_are_used_up() {
+        let quota = Quota{daily: None, monthly: Some(limit(None, Some(60)))};
+
+        let below = quota_usage(Default::default(), period_usage("2021-03", 0, 59_999));
+        assert!(check(&quota, &below, now()).is_ok());
+
+        let reached = quota_usage(Default::default(), period_usage("2021-03", 0, 60_000));
+        assert!(matches!(check(&quota, &reached, now()), Err(Error::RunSecondsExceeded(Period::Monthly, 60))));
+    }
+
+    #[test]
+    fn usage_from_previous_periods_is_ignored() {
+        let quota = Quota{daily: Some(limit(Some(1), None)), monthly: Some(limit(Some(1), None))};
+        let quota_usage = quota_usage(period_usage("2021-03-14", 5, 0), period_usage("2021-02", 5, 0));
+
+        assert!(check(&quota, &quota_usage, now()).is_ok());
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::time;
+
+
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimit {
+    pub requests_per_minute: u32,
+    pub burst: u32,
+}
+
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    default_limit: Option<RateLimit>,
+    buckets: HashMap<uuid::Uuid, Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: time::Instant,
+}
+
+
+impl RateLimiter {
+    pub fn new(default_limit: Option<RateLimit>) -> RateLimiter {
+        RateLimiter{
+            default_limit,
+            buckets: HashMap::new(),
+        }
+    }
+
+    // Takes a token from the users bucket or returns the time until the next token is available
+    pub fn acquire(&mut self, user_id: &uuid::Uuid, user_limit: Option<RateLimit>) -> Result<(), time::Duration> {
+        let limit = match user_limit.or(self.default_limit) {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        let capacity = f64::from(limit.burst.max(1));
+        let tokens_per_second = f64::from(limit.requests_per_minute) / 60.0;
+        let now = time::Instant::now();
+
+        let bucket = self.buckets.entry(*user_id).or_insert(Bucket{
+            tokens: capacity,
+            refilled: now,
+        });
+
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * tokens_per_second).min(capacity);
+        bucket.refilled = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else if tokens_per_second > 0.0 {
+            Err(time::Duration::from_secs_f64((1.0 - bucket.tokens) / tokens_per_second))
+        } else {
+            Err(time::Duration::from_secs(60))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(requests_per_minute: u32, burst: u32) -> RateLimit {
+        RateLimit{
+            requests_per_minute,
+            burst,
+        }
+    }
+
+    #[test]
+    fn allows_everything_without_a_limit() {
+        let mut limiter = RateLimiter::new(None);
+        let user_id = uuid::Uuid::new_v4();
+
+        for _ in 0..100 {
+            assert!(limiter.acquire(&user_id, None).is_ok());
+        }
+    }
+
+    #[test]
+    fn allows_burst_then_rejects() {
+        let mut limiter = RateLimiter::new(Some(limit(1, 3)));
+        let user_id = uuid::Uuid::new_v4();
+
+        for _ in 0..3 {
+            assert!(limiter.acquire(&user_id, None).is_ok());
+        }
+
+        let retry_after = limiter.acquire(&user_id, None).unwrap_err();
+        assert!(retry_after > time::Duration::from_secs(59));
+        assert!(retry_after <= time::Duration::from_secs(60));
+    }
+
+    #[test]
+    fn user_limit_overrides_default() {
+        let mut limiter = RateLimiter::new(Some(limit(0, 1)));
+        let user_id = uuid::Uuid::new_v4();
+
+        for _ in 0..5 {
+            assert!(limiter.acquire(&user_id, Some(limit(0, 5))).is_ok());
+        }
+
+        assert!(limiter.acquire(&user_id, Some(limit(0, 5))).is_err());
+    }
+
+    #[test]
+    fn zero_rate_waits_a_minute() {
+        let mut limiter = RateLimiter::new(Some(limit(0, 1)));
+        let user_id = uuid::Uuid::new_v4();
+
+        assert!(limiter.acquire(&user_id, None).is_ok());
+        assert_eq!(limiter.acquire(&user_id, None), Err(time::Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn users_have_separate_buckets() {
+        let mut limiter = RateLimiter::new(Some(limit(0, 1)));
+        let first_user = uuid::Uuid::new_v4();
+        let second_user = uuid::Uuid::new_v4();
+
+        assert!(limiter.acquire(&first_user, None).is_ok());
+        assert!(limiter.acquire(&first_user, None).is_err());
+        assert!(limiter.acquire(&second_user, None).is_ok());
+    }
+}
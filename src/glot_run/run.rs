@@ -26,7 +26,7 @@ pub struct File {
     pub content: String,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct RunResult {
     pub stdout: String,
     pub stderr: String,
@@ -45,8 +45,8 @@ impl Config {
     }
 }
 
-pub fn run(config: &Config, run_request: RunRequest) -> Result<RunResult, Error> {
-    let body = serde_json::to_vec(&run_request)
+pub fn run(config: &Config, run_request: &RunRequest) -> Result<RunResult, Error> {
+    let body = serde_json::to_vec(run_request)
         .map_err(Error::SerializeRequest)?;
 
     let response = ureq::post(&config.run_url())
@@ -75,6 +75,7 @@ fn check_response(response: ureq::Response) -> Result<ureq::Response, Error> {
 
             Err(Error::ResponseNotOk(api::ErrorResponse{
                 status_code,
+                headers: vec![],
                 body: error_body,
             }))
         }
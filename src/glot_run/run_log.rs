@@ -0,0 +1,337 @@
+use std::time;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use crate::glot_run::file;
+use crate::glot_run::run;
+use crate::glot_run::usage;
+use crate::glot_run::util;
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    // Only metadata like timing and sizes is stored
+    Metadata,
+    // The files and outputs are stored as well
+    Full,
+}
+
+impl FromStr for Mode {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Mode, ParseModeError> {
+        match s {
+            "metadata" => Ok(Mode::Metadata),
+            "full" => Ok(Mode::Full),
+            _ => Err(ParseModeError(s.to_string())),
+        }
+    }
+}
+
+pub struct ParseModeError(String);
+
+impl fmt::Display for ParseModeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid run log mode «{}», expected metadata or full", self.0)
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    // Runs are not logged when the mode is not set
+    pub mode: Option<Mode>,
+    pub max_entries: Option<usize>,
+    pub max_age: Option<time::Duration>,
+}
+
+
+// The run log file has its own lock so it doesn't hold up the datastore.
+// Appends and reads take the read lock since an entry is appended with a single write, pruning takes the write lock
+#[derive(Debug)]
+pub struct RunLog {
+    path: PathBuf,
+    lock: RwLock<()>,
+}
+
+impl RunLog {
+    pub fn new(path: PathBuf) -> RunLog {
+        RunLog{
+            path,
+            lock: RwLock::new(()),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub language: String,
+    pub version: String,
+    pub started: String,
+    pub wall_time_ms: u64,
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Details>,
+}
+
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Details {
+    pub files: Vec<run::File>,
+    pub stdin: Option<String>,
+    pub command: Option<String>,
+    pub result: Option<run::RunResult>,
+}
+
+
+pub struct RunInfo<'a> {
+    pub user_id: uuid::Uuid,
+    pub language: &'a str,
+    pub version: &'a str,
+    pub started: time::SystemTime,
+    pub counters: &'a usage::Counters,
+    pub error: Option<String>,
+    pub details: Details,
+}
+
+
+pub fn new(mode: Mode, info: RunInfo) -> Entry {
+    Entry{
+        id: uuid::Uuid::new_v4(),
+        user_id: info.user_id,
+        language: info.language.to_string(),
+        version: info.version.to_string(),
+        started: util::rfc3339(info.started),
+        wall_time_ms: info.counters.wall_time_ms,
+        input_bytes: info.counters.input_bytes,
+        output_bytes: info.counters.output_bytes,
+        error: info.error,
+        details: if mode == Mode::Full { Some(info.details) } else { None },
+    }
+}
+
+pub fn record(run_log: &RunLog, entry: &Entry) {
+    let _guard = run_log.lock.read().unwrap();
+
+    if let Err(err) = file::append_json_line(&run_log.path, entry) {
+        log::error!("Failed to record run {} for user {}: {}", entry.id, entry.user_id, err);
+    }
+}
+
+// Calls f with each entry, oldest first, until f returns false
+pub fn for_each_entry<F>(run_log: &RunLog, f: F) -> Result<(), file::ReadJsonError>
+    where F: FnMut(Entry) -> bool {
+
+    let _guard = run_log.lock.read().unwrap();
+
+    file::for_each_json_line(&run_log.path, f)
+}
+
+pub fn is_retained(config: &Config, entry: &Entry, now: time::SystemTime) -> bool {
+    match config.max_age {
+        Some(max_age) => {
+            util::parse_rfc3339(&entry.started)
+                .map(|started| started + max_age > now)
+                .unwrap_or(false)
+        }
+
+        None => {
+            true
+        }
+    }
+}
+
+// Removes entries that are older than the max age and the oldest entries above the max entries limit.
+// The log is read twice, first to count the retained entries and then to write them to a new file
+pub fn prune(run_log: &RunLog, config: &Config, now: time::SystemTime) -> Result<usize, PruneError> {
+    let _guard = run_log.lock.write().unwrap();
+
+    let mut count: usize = 0;
+    let mut retained_count: usize = 0;
+
+    file::for_each_json_line(&run_log.path, |entry: Entry| {
+        count += 1;
+
+        if is_retained(config, &entry, now) {
+            retained_count += 1;
+        }
+
+        true
+    }).map_err(PruneError::Read)?;
+
+    let excess = config.max_entries
+        .map(|max_entries| retained_count.saturating_sub(max_entries))
+        .unwrap_or(0);
+
+    let removed = count - retained_count + excess;
+
+    if removed == 0 {
+        return Ok(0)
+    }
+
+    let mut writer = file::JsonLinesWriter::new(&run_log.path)
+        .map_err(PruneError::Write)?;
+
+    let mut skipped = 0;
+    let mut write_result = Ok(());
+
+    file::for_each_json_line(&run_log.path, |entry: Entry| {
+        if !is_retained(config, &entry, now) {
+            return true
+        }
+
+        // The oldest retained entries are above the max entries limit
+        if skipped < excess {
+            skipped += 1;
+            return true
+        }
+
+        write_result = writer.write(&entry);
+        write_result.is_ok()
+    }).map_err(PruneError::Read)?;
+
+    write_result.map_err(PruneError::Write)?;
+
+    writer.persist()
+        .map_err(PruneError::Write)?;
+
+    Ok(removed)
+}
+
+
+pub enum PruneError {
+    Read(file::ReadJsonError),
+    Write(file::WriteJsonError),
+}
+
+impl fmt::Display for PruneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PruneError::Read(err) => {
+                write!(f, "Failed to read run log: {}", err)
+            }
+
+            PruneError::Write(err) => {
+                write!(f, "Failed to write run log: {}", err)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: time::Duration = time::Duration::from_secs(24 * 60 * 60);
+
+    fn entry(started: time::SystemTime) -> Entry {
+        new(Mode::Full, RunInfo{
+            user_id: uuid::Uuid::new_v4(),
+            language: "python",
+            version: "latest",
+            started,
+            counters: &usage::Counters::default(),
+            error: None,
+            details: Details{
+                files: vec![],
+                stdin: None,
+                command: None,
+                result: None,
+            },
+        })
+    }
+
+    fn config(max_entries: Option<usize>, max_age: Option<time::Duration>) -> Config {
+        Config{
+            mode: Some(Mode::Full),
+            max_entries,
+            max_age,
+        }
+    }
+
+    fn entry_ids(run_log: &RunLog) -> Vec<uuid::Uuid> {
+        let mut ids = Vec::new();
+
+        for_each_entry(run_log, |entry| {
+            ids.push(entry.id);
+            true
+        }).ok().unwrap();
+
+        ids
+    }
+
+    fn run_log_with_entries(dir: &tempfile::TempDir, entries: &[Entry]) -> RunLog {
+        let run_log = RunLog::new(dir.path().join("runs.jsonl"));
+
+        for entry in entries {
+            record(&run_log, entry);
+        }
+
+        run_log
+    }
+
+    #[test]
+    fn prune_removes_entries_older_than_max_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = time::SystemTime::now();
+        let old = entry(now - DAY * 3);
+        let new = entry(now - DAY);
+        let run_log = run_log_with_entries(&dir, &[old, new.clone()]);
+
+        assert_eq!(prune(&run_log, &config(None, Some(DAY * 2)), now).ok(), Some(1));
+        assert_eq!(entry_ids(&run_log), vec![new.id]);
+    }
+
+    #[test]
+    fn prune_removes_oldest_entries_above_max_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = time::SystemTime::now();
+        let entries = (0..5).map(|_| entry(now)).collect::<Vec<Entry>>();
+        let run_log = run_log_with_entries(&dir, &entries);
+
+        assert_eq!(prune(&run_log, &config(Some(2), None), now).ok(), Some(3));
+        assert_eq!(entry_ids(&run_log), vec![entries[3].id, entries[4].id]);
+    }
+
+    #[test]
+    fn prune_counts_max_entries_after_max_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = time::SystemTime::now();
+        let entries = vec![entry(now - DAY * 3), entry(now), entry(now), entry(now - DAY * 3), entry(now)];
+        let run_log = run_log_with_entries(&dir, &entries);
+
+        assert_eq!(prune(&run_log, &config(Some(2), Some(DAY)), now).ok(), Some(3));
+        assert_eq!(entry_ids(&run_log), vec![entries[2].id, entries[4].id]);
+    }
+
+    #[test]
+    fn prune_keeps_file_when_nothing_is_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = time::SystemTime::now();
+        let entries = vec![entry(now), entry(now)];
+        let run_log = run_log_with_entries(&dir, &entries);
+
+        assert_eq!(prune(&run_log, &config(Some(2), Some(DAY)), now).ok(), Some(0));
+        assert_eq!(entry_ids(&run_log), vec![entries[0].id, entries[1].id]);
+    }
+
+    #[test]
+    fn prune_handles_missing_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let run_log = RunLog::new(dir.path().join("runs.jsonl"));
+
+        assert_eq!(prune(&run_log, &config(Some(2), Some(DAY)), time::SystemTime::now()).ok(), Some(0));
+        assert!(entry_ids(&run_log).is_empty());
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::fs;
+use std::time;
+
+use crate::glot_run::file;
+use crate::glot_run::storage;
+use crate::glot_run::backup;
+
+
+
+// Stores each table as a json file in <root>/<table>.json
+#[derive(Debug)]
+pub struct JsonFileStorage {
+    root: PathBuf,
+    backups: backup::Rotation,
+    // Parsed tables, a table is read again when the file has been modified outside of this storage
+    cache: RwLock<HashMap<String, CachedTable>>,
+}
+
+// Files written before tables were versioned only contain the entries and have version 0
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum TableFile {
+    Versioned {
+        version: u32,
+        entries: storage::Entries,
+    },
+    Unversioned(storage::Entries),
+}
+
+
+#[derive(Debug)]
+struct CachedTable {
+    file_version: FileVersion,
+    schema_version: u32,
+    entries: storage::Entries,
+    // Maps index keys to entry keys, an index is built on first lookup
+    indexes: Mutex<HashMap<&'static str, Arc<HashMap<String, String>>>>,
+}
+
+impl CachedTable {
+    fn new(file_version: FileVersion, table_file: TableFile) -> CachedTable {
+        let (schema_version, entries) = match table_file {
+            TableFile::Versioned{version, entries} => (version, entries),
+            TableFile::Unversioned(entries) => (0, entries),
+        };
+
+        CachedTable{
+            file_version,
+            schema_version,
+            entries,
+            indexes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn index(&self, index: &storage::Index) -> Arc<HashMap<String, String>> {
+        let mut indexes = self.indexes.lock().unwrap();
+
+        let index_entries = indexes.entry(index.name).or_insert_with(|| {
+            let index_entries = self.entries.iter()
+                .flat_map(|(key, value)| {
+                    (index.keys)(value).into_iter().map(move |index_key| (index_key, key.clone()))
+                })
+                .collect();
+
+            Arc::new(index_entries)
+        });
+
+        Arc::clone(index_entries)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct FileVersion {
+    modified: time::SystemTime,
+    len: u64,
+}
+
+impl JsonFileStorage {
+    pub fn new(root: PathBuf, backup_config: backup::Config) -> JsonFileStorage {
+        JsonFileStorage{
+            root,
+            backups: backup::Rotation::new(backup_config),
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn table_path(&self, table: &str) -> PathBuf {
+        self.root.join(format!("{}.json", table))
+    }
+
+    fn file_version(&self, table: &str) -> Result<FileVersion, storage::Error> {
+        let metadata = fs::metadata(self.table_path(table))
+            .map_err(|err| storage::Error::ReadFile(file::ReadJsonError::Open(err)))?;
+
+        let modified = metadata.modified()
+            .map_err(|err| storage::Error::ReadFile(file::ReadJsonError::Open(err)))?;
+
+        Ok(FileVersion{
+            modified,
+            len: metadata.len(),
+        })
+    }
+
+    fn with_table<T, F>(&self, table: &str, f: F) -> Result<T, storage::Error>
+        where F: FnOnce(&CachedTable) -> T {
+
+        let file_version = self.file_version(table)?;
+
+        {
+            let cache = self.cache.read().unwrap();
+
+            if let Some(cached) = cache.get(table).filter(|cached| cached.file_version == file_version) {
+                return Ok(f(cached))
+            }
+        }
+
+        let mut cache = self.cache.write().unwrap();
+
+        // Another thread might have read the file while waiting for the write lock
+        let is_current = cache.get(table)
+            .map(|cached| cached.file_version == file_version)
+            .unwrap_or(false);
+
+        if !is_current {
+            let table_file = file::read_json(&self.table_path(table))
+                .map_err(storage::Error::ReadFile)?;
+
+            cache.insert(table.to_string(), CachedTable::new(file_version, table_file));
+        }
+
+        // The table was inserted above if it was missing
+        Ok(f(&cache[table]))
+    }
+
+    fn update_table<F>(&self, table: &str, update_fn: F) -> Result<(), storage::Error>
+        where F: FnOnce(&mut storage::Entries, &mut u32) {
+
+        let (mut entries, mut schema_version) = self.with_table(table, |cached| {
+            (cached.entries.clone(), cached.schema_version)
+        })?;
+
+        update_fn(&mut entries, &mut schema_version);
+
+        self.write_table(table, TableFile::Versioned{
+            version: schema_version,
+            entries,
+        })
+    }
+
+    // Writes to the same table are serialized by the caller, the cache is only locked after the file is written
+    // so reads of other tables don't wait for the write
+    fn write_table(&self, table: &str, table_file: TableFile) -> Result<(), storage::Error> {
+        let path = self.table_path(table);
+
+        storage::rotate_backup(&self.backups, &path, |backup_path| {
+            fs::copy(&path, backup_path).map(|_| ())
+        });
+
+        file::write_json(&path, &table_file)
+            .map_err(storage::Error::WriteFile)?;
+
+        let file_version = self.file_version(table)?;
+
+        let mut cache = self.cache.write().unwrap();
+        cache.insert(table.to_string(), CachedTable::new(file_version, table_file));
+
+        Ok(())
+    }
+}
+
+impl storage::Storage for JsonFileStorage {
+    fn init(&self, table: &str) -> Result<(), storage::Error> {
+        if !self.table_path(table).exists() {
+            self.write_table(table, TableFile::Versioned{
+                version: 0,
+                entries: HashMap::new(),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn exists(&self, table: &str) -> Result<bool, storage::Error> {
+        Ok(self.table_path(table).exists())
+    }
+
+    fn get(&self, table: &str, key: &str) -> Result<Option<serde_json::Value>, storage::Error> {
+        self.with_table(table, |cached| cached.entries.get(key).cloned())
+    }
+
+    fn list(&self, table: &str) -> Result<storage::Entries, storage::Error> {
+        self.with_table(table, |cached| cached.entries.clone())
+    }
+
+    fn put(&self, table: &str, new_entries: storage::Entries) -> Result<(), storage::Error> {
+        self.update_table(table, |entries, _| entries.extend(new_entries))
+    }
+
+    fn remove(&self, table: &str, key: &str) -> Result<(), storage::Error> {
+        self.update_table(table, |entries, _| {
+            entries.remove(key);
+        })
+    }
+
+    fn replace(&self, table: &str, new_entries: storage::Entries) -> Result<(), storage::Error> {
+        self.update_table(table, |entries, _| *entries = new_entries)
+    }
+
+    fn schema_version(&self, table: &str) -> Result<u32, storage::Error> {
+        self.with_table(table, |cached| cached.schema_version)
+    }
+
+    fn migrate(&self, table: &str, new_entries: storage::Entries, version: u32) -> Result<(), storage::Error> {
+        self.update_table(table, |entries, schema_version| {
+            entries.extend(new_entries);
+            *schema_version = version;
+        })
+    }
+
+    fn lookup(&self, table: &str, index: &storage::Index, index_key: &str) -> Result<Option<serde_json::Value>, storage::Error> {
+        self.with_table(table, |cached| {
+            cached.index(index).get(index_key)
+                .and_then(|key| cached.entries.get(key))
+                .cloned()
+        })
+    }
+}
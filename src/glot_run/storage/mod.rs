@@ -0,0 +1,150 @@
+pub mod json_file;
+pub mod sqlite;
+
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time;
+use std::fmt;
+
+use crate::glot_run::file;
+use crate::glot_run::backup;
+
+
+
+pub type Entries = HashMap<String, serde_json::Value>;
+
+
+// Secondary index of a table, keys returns the index keys of an entry
+#[derive(Debug, Clone, Copy)]
+pub struct Index {
+    pub name: &'static str,
+    pub keys: fn(&serde_json::Value) -> Vec<String>,
+}
+
+
+// A storage holds a set of named tables, each table maps keys to json values
+pub trait Storage: fmt::Debug + Send + Sync {
+    fn init(&self, table: &str) -> Result<(), Error>;
+    // Checks if the table has been created, without creating anything
+    fn exists(&self, table: &str) -> Result<bool, Error>;
+    fn get(&self, table: &str, key: &str) -> Result<Option<serde_json::Value>, Error>;
+    fn list(&self, table: &str) -> Result<Entries, Error>;
+    // All entries are inserted or replaced atomically
+    fn put(&self, table: &str, entries: Entries) -> Result<(), Error>;
+    fn remove(&self, table: &str, key: &str) -> Result<(), Error>;
+    // Replaces all entries of the table atomically
+    fn replace(&self, table: &str, entries: Entries) -> Result<(), Error>;
+    // Version of the entry format, it is 0 for new tables
+    fn schema_version(&self, table: &str) -> Result<u32, Error>;
+    // Inserts or replaces the entries and sets the schema version atomically
+    fn migrate(&self, table: &str, entries: Entries, version: u32) -> Result<(), Error>;
+
+    // Returns the first entry with the given index key
+    fn lookup(&self, table: &str, index: &Index, index_key: &str) -> Result<Option<serde_json::Value>, Error> {
+        let entries = self.list(table)?;
+
+        Ok(entries.into_values().find(|value| {
+            (index.keys)(value).iter().any(|key| key == index_key)
+        }))
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    JsonFile,
+    Sqlite,
+}
+
+impl FromStr for Backend {
+    type Err = ParseBackendError;
+
+    fn from_str(s: &str) -> Result<Backend, ParseBackendError> {
+        match s {
+            "json" => Ok(Backend::JsonFile),
+            "sqlite" => Ok(Backend::Sqlite),
+            _ => Err(ParseBackendError(s.to_string())),
+        }
+    }
+}
+
+pub struct ParseBackendError(String);
+
+impl fmt::Display for ParseBackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid datastore backend «{}», expected json or sqlite", self.0)
+    }
+}
+
+
+// Indexes are given as (table, index) pairs, the json file storage builds them on first lookup instead
+pub fn new(backend: Backend, root: &Path, backup_config: backup::Config, indexes: &[(&'static str, Index)]) -> Arc<dyn Storage> {
+    match backend {
+        Backend::JsonFile => {
+            Arc::new(json_file::JsonFileStorage::new(root.to_path_buf(), backup_config))
+        }
+
+        Backend::Sqlite => {
+            Arc::new(sqlite::SqliteStorage::new(root.join("glot.sqlite"), backup_config, indexes))
+        }
+    }
+}
+
+// Backups are best effort, a failed backup should not fail the write
+pub fn rotate_backup<F>(backups: &backup::Rotation, path: &Path, copy_fn: F)
+    where F: FnOnce(&Path) -> Result<(), io::Error> {
+
+    if let Err(err) = backup::rotate(backups, path, time::SystemTime::now(), copy_fn) {
+        log::error!("Failed to back up {}: {}", path.display(), err);
+    }
+}
+
+// Copies all entries and the schema version of a table from one storage to another, existing entries with the same key are replaced
+pub fn copy_table(from: &dyn Storage, to: &dyn Storage, table: &str) -> Result<usize, Error> {
+    let entries = from.list(table)?;
+    let version = from.schema_version(table)?;
+    let count = entries.len();
+
+    to.init(table)?;
+    to.migrate(table, entries, version)?;
+
+    Ok(count)
+}
+
+
+pub enum Error {
+    ReadFile(file::ReadJsonError),
+    WriteFile(file::WriteJsonError),
+    Sqlite(rusqlite::Error),
+    Serialize(serde_json::Error),
+    Deserialize(serde_json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ReadFile(err) => {
+                write!(f, "{}", err)
+            }
+
+            Error::WriteFile(err) => {
+                write!(f, "{}", err)
+            }
+
+            Error::Sqlite(err) => {
+                write!(f, "Sqlite error: {}", err)
+            }
+
+            Error::Serialize(err) => {
+                write!(f, "Failed to serialize entry: {}", err)
+            }
+
+            Error::Deserialize(err) => {
+                write!(f, "Failed to deserialize entry: {}", err)
+            }
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time;
+
+use crate::glot_run::storage;
+use crate::glot_run::backup;
+
+
+// How long a connection waits for a lock held by another connection, i.e. the migrate command
+const SQLITE_BUSY_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+
+// Stores each table as a sqlite table with a key and a json value column.
+// Each index of a table is stored in a <table>_<index> table that maps index keys to entry keys,
+// it is written in the same transaction as the entries
+#[derive(Debug)]
+pub struct SqliteStorage {
+    path: PathBuf,
+    backups: backup::Rotation,
+    indexes: HashMap<&'static str, Vec<storage::Index>>,
+    // Idle connections, a new connection is opened when none is idle so readers don't block each other
+    connections: Mutex<Vec<rusqlite::Connection>>,
+}
+
+impl SqliteStorage {
+    pub fn new(path: PathBuf, backup_config: backup::Config, indexes: &[(&'static str, storage::Index)]) -> SqliteStorage {
+        let mut table_indexes: HashMap<&'static str, Vec<storage::Index>> = HashMap::new();
+
+        for (table, index) in indexes {
+            table_indexes.entry(table).or_default().push(*index);
+        }
+
+        SqliteStorage{
+            path,
+            backups: backup::Rotation::new(backup_config),
+            indexes: table_indexes,
+            connections: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn table_indexes(&self, table: &str) -> &[storage::Index] {
+        self.indexes.get(table)
+            .map(|indexes| indexes.as_slice())
+            .unwrap_or(&[])
+    }
+
+    fn with_connection<T, F>(&self, f: F) -> Result<T, storage::Error>
+        where F: FnOnce(&mut rusqlite::Connection) -> Result<T, rusqlite::Error> {
+
+        let idle_connection = self.connections.lock().unwrap().pop();
+
+        let mut connection = match idle_connection {
+            Some(connection) => connection,
+            None => self.open()?,
+        };
+
+        let result = f(&mut connection);
+
+        self.connections.lock().unwrap().push(connection);
+
+        result.map_err(storage::Error::Sqlite)
+    }
+
+    // Inserts or replaces the entries and optionally removes all other entries and sets the schema version in one transaction
+    fn write(&self, table: &str, entries: storage::Entries, replace: bool, schema_version: Option<u32>) -> Result<(), storage::Error> {
+        let indexes = self.table_indexes(table);
+
+        let index_keys = entries.iter()
+            .map(|(key, value)| {
+                let keys = indexes.iter()
+                    .map(|index| (index, (index.keys)(value)))
+                    .collect::<Vec<(&storage::Index, Vec<String>)>>();
+
+                (key.clone(), keys)
+            })
+            .collect::<Vec<(String, Vec<(&storage::Index, Vec<String>)>)>>();
+
+        let rows = entries.into_iter()
+            .map(|(key, value)| {
+                serde_json::to_string(&value)
+                    .map(|value| (key, value))
+                    .map_err(storage::Error::Serialize)
+            })
+            .collect::<Result<Vec<(String, String)>, storage::Error>>()?;
+
+        self.backup();
+
+        self.with_connection(|connection| {
+            let transaction = connection.transaction()?;
+
+            if replace {
+                transaction.execute(&format!("DELETE FROM \"{}\"", table), rusqlite::NO_PARAMS)?;
+
+                for index in indexes {
+                    transaction.execute(&format!("DELETE FROM \"{}\"", index_table(table, index)), rusqlite::NO_PARAMS)?;
+                }
+            }
+
+            {
+                let mut statement = transaction.prepare_cached(&format!(
+                    "INSERT INTO \"{}\" (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                    table,
+                ))?;
+
+                for (key, value) in &rows {
+                    statement.execute(rusqlite::params![key, value])?;
+                }
+            }
+
+            for (key, keys) in &index_keys {
+                for (index, keys) in keys {
+                    write_index_keys(&transaction, table, index, key, keys)?;
+                }
+            }
+
+            if let Some(version) = schema_version {
+                transaction.execute(
+                    "INSERT INTO schema_versions (name, version) VALUES (?1, ?2) ON CONFLICT(name) DO UPDATE SET version = excluded.version",
+                    rusqlite::params![table, version],
+                )?;
+            }
+
+            transaction.commit()
+        })
+    }
+
+    // Other connections can be in a write transaction, so the file is not copied directly.
+    // VACUUM INTO writes a consistent snapshot of the database to the backup path
+    fn backup(&self) {
+        storage::rotate_backup(&self.backups, &self.path, |backup_path| {
+            self.with_connection(|connection| {
+                connection.execute("VACUUM INTO ?1", rusqlite::params![backup_path.to_string_lossy()])
+                    .map(|_| ())
+            }).map_err(|err| io::Error::other(err.to_string()))
+        });
+    }
+
+    fn open(&self) -> Result<rusqlite::Connection, storage::Error> {
+        let connection = rusqlite::Connection::open(&self.path)
+            .map_err(storage::Error::Sqlite)?;
+
+        connection.busy_timeout(SQLITE_BUSY_TIMEOUT)
+            .map_err(storage::Error::Sqlite)?;
+
+        Ok(connection)
+    }
+}
+
+impl storage::Storage for SqliteStorage {
+    // The indexes are rebuilt, so they are complete even if the table was written without them
+    fn init(&self, table: &str) -> Result<(), storage::Error> {
+        let indexes = self.table_indexes(table);
+
+        self.with_connection(|connection| {
+            connection.execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS schema_versions (name TEXT PRIMARY KEY NOT NULL, version INTEGER NOT NULL);
+                 CREATE TABLE IF NOT EXISTS \"{}\" (key TEXT PRIMARY KEY NOT NULL, value TEXT NOT NULL);",
+                table,
+            ))?;
+
+            if indexes.is_empty() {
+                return Ok(())
+            }
+
+            let transaction = connection.transaction()?;
+
+            let rows: Vec<(String, String)> = {
+                let mut statement = transaction.prepare(&format!("SELECT key, value FROM \"{}\"", table))?;
+                let rows = statement.query_map(rusqlite::NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+                rows.collect::<Result<_, _>>()?
+            };
+
+            for index in indexes {
+                let index_table = index_table(table, index);
+
+                transaction.execute_batch(&format!(
+                    "CREATE TABLE IF NOT EXISTS \"{0}\" (index_key TEXT PRIMARY KEY NOT NULL, key TEXT NOT NULL);
+                     CREATE INDEX IF NOT EXISTS \"{0}_key\" ON \"{0}\" (key);
+                     DELETE FROM \"{0}\";",
+                    index_table,
+                ))?;
+
+                for (key, value) in &rows {
+                    // Entries that can't be parsed have no index keys, list reports them
+                    let keys = serde_json::from_str(value)
+                        .map(|value| (index.keys)(&value))
+                        .unwrap_or_default();
+
+                    write_index_keys(&transaction, table, index, key, &keys)?;
+                }
+            }
+
+            transaction.commit()
+        })
+    }
+
+    fn exists(&self, table: &str) -> Result<bool, storage::Error> {
+        // Opening a connection creates the database file
+        if !self.path.exists() {
+            return Ok(false)
+        }
+
+        self.with_connection(|connection| {
+            let mut statement = connection.prepare_cached("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1")?;
+            let mut rows = statement.query(rusqlite::params![table])?;
+
+            rows.next().map(|row| row.is_some())
+        })
+    }
+
+    fn get(&self, table: &str, key: &str) -> Result<Option<serde_json::Value>, storage::Error> {
+        let value: Option<String> = self.with_connection(|connection| {
+            let mut statement = connection.prepare_cached(&format!("SELECT value FROM \"{}\" WHERE key = ?1", table))?;
+            let mut rows = statement.query(rusqlite::params![key])?;
+
+            match rows.next()? {
+                Some(row) => row.get(0).map(Some),
+                None => Ok(None),
+            }
+        })?;
+
+        value
+            .map(|value| serde_json::from_str(&value))
+            .transpose()
+            .map_err(storage::Error::Deserialize)
+    }
+
+    fn list(&self, table: &str) -> Result<storage::Entries, storage::Error> {
+        let rows: Vec<(String, String)> = self.with_connection(|connection| {
+            let mut statement = connection.prepare_cached(&format!("SELECT key, value FROM \"{}\"", table))?;
+            let rows = statement.query_map(rusqlite::NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+            rows.collect()
+        })?;
+
+        rows.into_iter()
+            .map(|(key, value)| {
+                serde_json::from_str(&value)
+                    .map(|value| (key, value))
+                    .map_err(storage::Error::Deserialize)
+            })
+            .collect()
+    }
+
+    fn put(&self, table: &str, entries: storage::Entries) -> Result<(), storage::Error> {
+        self.write(table, entries, false, None)
+    }
+
+    fn remove(&self, table: &str, key: &str) -> Result<(), storage::Error> {
+        let indexes = self.table_indexes(table);
+
+        self.backup();
+
+        self.with_connection(|connection| {
+            let transaction = connection.transaction()?;
+
+            transaction.execute(&format!("DELETE FROM \"{}\" WHERE key = ?1", table), rusqlite::params![key])?;
+
+            for index in indexes {
+                write_index_keys(&transaction, table, index, key, &[])?;
+            }
+
+            transaction.commit()
+        })
+    }
+
+    fn replace(&self, table: &str, entries: storage::Entries) -> Result<(), storage::Error> {
+        self.write(table, entries, true, None)
+    }
+
+    fn schema_version(&self, table: &str) -> Result<u32, storage::Error> {
+        self.with_connection(|connection| {
+            let mut statement = connection.prepare_cached("SELECT version FROM schema_versions WHERE name = ?1")?;
+            let mut rows = statement.query(rusqlite::params![table])?;
+
+            match rows.next()? {
+                Some(row) => row.get(0),
+                None => Ok(0),
+            }
+        })
+    }
+
+    fn migrate(&self, table: &str, entries: storage::Entries, version: u32) -> Result<(), storage::Error> {
+        self.write(table, entries, false, Some(version))
+    }
+
+    fn lookup(&self, table: &str, index: &storage::Index, index_key: &str) -> Result<Option<serde_json::Value>, storage::Error> {
+        let is_stored = self.table_indexes(table).iter().any(|stored| stored.name == index.name);
+
+        // Only the indexes given to new are stored
+        if !is_stored {
+            let entries = self.list(table)?;
+
+            return Ok(entries.into_values().find(|value| {
+                (index.keys)(value).iter().any(|key| key == index_key)
+            }))
+        }
+
+        let value: Option<String> = self.with_connection(|connection| {
+            let mut statement = connection.prepare_cached(&format!(
+                "SELECT entries.value FROM \"{}\" AS entries JOIN \"{}\" AS index_keys ON entries.key = index_keys.key WHERE index_keys.index_key = ?1",
+                table,
+                index_table(table, index),
+            ))?;
+
+            let mut rows = statement.query(rusqlite::params![index_key])?;
+
+            match rows.next()? {
+                Some(row) => row.get(0).map(Some),
+                None => Ok(None),
+            }
+        })?;
+
+        value
+            .map(|value| serde_json::from_str(&value))
+            .transpose()
+            .map_err(storage::Error::Deserialize)
+    }
+}
+
+
+fn index_table(table: &str, index: &storage::Index) -> String {
+    format!("{}_{}", table, index.name)
+}
+
+// Replaces the index keys of an entry, an index key that is used by another entry is taken over
+fn write_index_keys(transaction: &rusqlite::Transaction, table: &str, index: &storage::Index, key: &str, index_keys: &[String]) -> Result<(), rusqlite::Error> {
+    let index_table = index_table(table, index);
+
+    transaction.execute(&format!("DELETE FROM \"{}\" WHERE key = ?1", index_table), rusqlite::params![key])?;
+
+    let mut statement = transaction.prepare_cached(&format!(
+        "INSERT INTO \"{}\" (index_key, key) VALUES (?1, ?2) ON CONFLICT(index_key) DO UPDATE SET key = excluded.key",
+        index_table,
+    ))?;
+
+    for index_key in index_keys {
+        statement.execute(rusqlite::params![index_key, key])?;
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glot_run::storage::Storage;
+
+    const NAME_INDEX: storage::Index = storage::Index{
+        name: "name",
+        keys: names,
+    };
+
+    fn names(entry: &serde_json::Value) -> Vec<String> {
+        entry.get("names")
+            .and_then(|names| names.as_array())
+            .map(|names| names.iter().filter_map(|name| Some(name.as_str()?.to_string())).collect())
+            .unwrap_or_default()
+    }
+
+    fn storage(dir: &tempfile::TempDir, indexes: &[(&'static str, storage::Index)]) -> SqliteStorage {
+        SqliteStorage::new(dir.path().join("glot.sqlite"), backup::Config{
+            dir: dir.path().join("backups"),
+            count: 0,
+            interval: time::Duration::from_secs(0),
+        }, indexes)
+    }
+
+    fn entries(entries: &[(&str, serde_json::Value)]) -> storage::Entries {
+        entries.iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect()
+    }
+
+    fn lookup_key(storage: &SqliteStorage, name: &str) -> Option<String> {
+        storage.lookup("entries", &NAME_INDEX, name).ok().unwrap()
+            .and_then(|value| Some(value.get("key")?.as_str()?.to_string()))
+    }
+
+    #[test]
+    fn lookup_follows_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(&dir, &[("entries", NAME_INDEX)]);
+
+        storage.init("entries").ok().unwrap();
+        storage.put("entries", entries(&[
+            ("a", serde_json::json!({"key": "a", "names": ["x", "y"]})),
+            ("b", serde_json::json!({"key": "b", "names": ["z"]})),
+        ])).ok().unwrap();
+
+        assert_eq!(lookup_key(&storage, "x"), Some("a".to_string()));
+        assert_eq!(lookup_key(&storage, "z"), Some("b".to_string()));
+        assert_eq!(lookup_key(&storage, "w"), None);
+
+        storage.put("entries", entries(&[("a", serde_json::json!({"key": "a", "names": ["w"]}))])).ok().unwrap();
+        assert_eq!(lookup_key(&storage, "x"), None);
+        assert_eq!(lookup_key(&storage, "w"), Some("a".to_string()));
+
+        storage.remove("entries", "b").ok().unwrap();
+        assert_eq!(lookup_key(&storage, "z"), None);
+
+        storage.replace("entries", entries(&[("c", serde_json::json!({"key": "c", "names": ["y"]}))])).ok().unwrap();
+        assert_eq!(lookup_key(&storage, "w"), None);
+        assert_eq!(lookup_key(&storage, "y"), Some("c".to_string()));
+    }
+
+    #[test]
+    fn init_builds_index_of_existing_entries() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let unindexed = storage(&dir, &[]);
+        unindexed.init("entries").ok().unwrap();
+        unindexed.put("entries", entries(&[("a", serde_json::json!({"key": "a", "names": ["x"]}))])).ok().unwrap();
+
+        // Lookups without a stored index scan the table
+        assert_eq!(lookup_key(&unindexed, "x"), Some("a".to_string()));
+
+        let indexed = storage(&dir, &[("entries", NAME_INDEX)]);
+        indexed.init("entries").ok().unwrap();
+
+        assert_eq!(lookup_key(&indexed, "x"), Some("a".to_string()));
+    }
+}
@@ -0,0 +1,83 @@
+use hmac::Mac;
+use hmac::NewMac;
+use subtle::ConstantTimeEq;
+use rand::Rng;
+
+
+type HmacSha256 = hmac::Hmac<sha2::Sha256>;
+
+const GENERATED_TOKEN_LENGTH: usize = 40;
+
+
+pub fn generate(prefix: &ascii::AsciiStr) -> ascii::AsciiString {
+    let mut token = prefix.to_ascii_string();
+
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(GENERATED_TOKEN_LENGTH)
+        .for_each(|byte| token.push(ascii::AsciiChar::from_ascii(byte).unwrap()));
+
+    token
+}
+
+
+pub fn hash(key: &ascii::AsciiString, token: &str) -> String {
+    // Hmac accepts keys of any length
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).unwrap();
+    mac.update(token.as_bytes());
+
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// Identifies a token hash key without revealing it
+pub fn key_fingerprint(key: &ascii::AsciiString) -> String {
+    hash(key, "glot-run token hash key fingerprint")
+}
+
+pub fn hash_eq(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(value: &str) -> ascii::AsciiString {
+        ascii::AsciiString::from_ascii(value).unwrap()
+    }
+
+    #[test]
+    fn generated_tokens_have_prefix_and_length() {
+        let prefix = key("glot_");
+        let token = generate(&prefix);
+
+        assert!(token.as_str().starts_with("glot_"));
+        assert_eq!(token.len(), prefix.len() + GENERATED_TOKEN_LENGTH);
+        assert_ne!(token, generate(&prefix));
+    }
+
+    #[test]
+    fn hash_depends_on_key_and_token() {
+        let hash = hash(&key("key"), "token");
+
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, super::hash(&key("key"), "token"));
+        assert_ne!(hash, super::hash(&key("other key"), "token"));
+        assert_ne!(hash, super::hash(&key("key"), "other token"));
+        assert_ne!(hash, "token");
+    }
+
+    #[test]
+    fn key_fingerprint_identifies_key() {
+        assert_eq!(key_fingerprint(&key("key")), key_fingerprint(&key("key")));
+        assert_ne!(key_fingerprint(&key("key")), key_fingerprint(&key("other key")));
+    }
+
+    #[test]
+    fn hash_eq_compares_contents() {
+        assert!(hash_eq("abc", "abc"));
+        assert!(!hash_eq("abc", "abd"));
+        assert!(!hash_eq("abc", "abcd"));
+    }
+}
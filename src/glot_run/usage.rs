@@ -0,0 +1,324 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time;
+
+use crate::glot_run::config;
+use crate::glot_run::datastore;
+use crate::glot_run::util;
+use crate::glot_run::user;
+use crate::glot_run::run;
+
+
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    pub user_id: uuid::Uuid,
+    pub days: BTreeMap<String, Counters>,
+    #[serde(default)]
+    pub quota_usage: QuotaUsage,
+}
+
+
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Counters {
+    pub runs: u64,
+    pub wall_time_ms: u64,
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+}
+
+
+// Counters for the current quota periods, these can be reset independently of the daily buckets
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaUsage {
+    pub daily: PeriodUsage,
+    pub monthly: PeriodUsage,
+}
+
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeriodUsage {
+    pub period: String,
+    pub runs: u64,
+    pub wall_time_ms: u64,
+}
+
+
+// Usage of runs that has not been written to the datastore yet.
+// Runs only add to it so they don't need the datastore write lock, flush writes it in one write
+#[derive(Debug, Default)]
+pub struct Pending {
+    runs: Mutex<HashMap<uuid::Uuid, Vec<Run>>>,
+    // Only one flush writes to the usage table at a time
+    flush_lock: Mutex<()>,
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct Run {
+    pub finished: time::SystemTime,
+    pub counters: Counters,
+}
+
+
+impl Pending {
+    pub fn add(&self, user_id: &uuid::Uuid, finished: time::SystemTime, counters: &Counters) {
+        let mut runs = self.runs.lock().unwrap();
+
+        runs.entry(*user_id).or_default().push(Run{
+            finished,
+            counters: *counters,
+        });
+    }
+
+    // Returns the usage with the pending runs of the user added
+    pub fn apply(&self, usage: &Usage, retention: time::Duration) -> Usage {
+        let runs = self.runs.lock().unwrap();
+        let user_runs = runs.get(&usage.user_id).map(|user_runs| user_runs.as_slice()).unwrap_or(&[]);
+
+        add_runs(usage, user_runs, retention)
+    }
+
+    // Removes and returns the pending runs of a user, the caller must hold the datastore write lock
+    pub fn take(&self, user_id: &uuid::Uuid) -> Vec<Run> {
+        self.runs.lock().unwrap().remove(user_id).unwrap_or_default()
+    }
+}
+
+
+pub fn new(user_id: &uuid::Uuid) -> Usage {
+    Usage{
+        user_id: *user_id,
+        days: BTreeMap::new(),
+        quota_usage: QuotaUsage::default(),
+    }
+}
+
+pub fn input_bytes(files: &[run::File]) -> u64 {
+    files.iter()
+        .map(|file| file.content.len() as u64)
+        .sum()
+}
+
+pub fn run_counters(input_bytes: u64, run_result: Option<&run::RunResult>, wall_time: time::Duration) -> Counters {
+    let output_bytes = run_result
+        .map(|result| (result.stdout.len() + result.stderr.len()) as u64)
+        .unwrap_or(0);
+
+    Counters{
+        runs: 1,
+        wall_time_ms: wall_time.as_millis() as u64,
+        input_bytes,
+        output_bytes,
+    }
+}
+
+// Daily buckets older than the retention period are removed
+pub fn add(usage: &Usage, now: time::SystemTime, counters: &Counters, retention: time::Duration) -> Usage {
+    // Dates sort the same way as strings
+    let oldest_day = util::date(now.checked_sub(retention).unwrap_or(time::UNIX_EPOCH));
+    let mut days = usage.days.clone().split_off(&oldest_day);
+    let day = days.entry(util::date(now)).or_default();
+
+    day.runs += counters.runs;
+    day.wall_time_ms += counters.wall_time_ms;
+    day.input_bytes += counters.input_bytes;
+    day.output_bytes += counters.output_bytes;
+
+    let quota_usage = QuotaUsage{
+        daily: add_period(&usage.quota_usage.daily, &util::date(now), counters),
+        monthly: add_period(&usage.quota_usage.monthly, &util::month(now), counters),
+    };
+
+    Usage{
+        days,
+        quota_usage,
+        ..usage.clone()
+    }
+}
+
+pub fn add_runs(usage: &Usage, runs: &[Run], retention: time::Duration) -> Usage {
+    runs.iter().fold(usage.clone(), |usage, run| {
+        add(&usage, run.finished, &run.counters, retention)
+    })
+}
+
+pub fn reset_quota_usage(usage: &Usage) -> Usage {
+    Usage{
+        quota_usage: QuotaUsage::default(),
+        ..usage.clone()
+    }
+}
+
+pub fn current_period(period_usage: &PeriodUsage, period: &str) -> PeriodUsage {
+    if period_usage.period == period {
+        period_usage.clone()
+    } else {
+        PeriodUsage{
+            period: period.to_string(),
+            runs: 0,
+            wall_time_ms: 0,
+        }
+    }
+}
+
+fn add_period(period_usage: &PeriodUsage, period: &str, counters: &Counters) -> PeriodUsage {
+    let current = current_period(period_usage, period);
+
+    PeriodUsage{
+        runs: current.runs + counters.runs,
+        wall_time_ms: current.wall_time_ms + counters.wall_time_ms,
+        ..current
+    }
+}
+
+// Writes the pending runs to the usage table in one write and returns the number of runs written.
+// The caller must hold at least the datastore read lock, all other writes to the usage table take the write lock.
+// Runs of users that have been deleted are dropped
+pub fn flush(data_root: &config::DataRoot, pending: &Pending, retention: time::Duration) -> Result<usize, datastore::AddError> {
+    let _flush_guard = pending.flush_lock.lock().unwrap();
+    let runs = pending.runs.lock().unwrap().clone();
+
+    let mut entries = Vec::new();
+
+    for (user_id, user_runs) in &runs {
+        match datastore::get_entry::<user::User>(&data_root.users(), &user_id.to_string()) {
+            Ok(_) => {}
+            Err(datastore::GetError::NotFound()) => continue,
+            Err(datastore::GetError::Read(err)) => return Err(datastore::AddError::Read(err)),
+        }
+
+        let user_usage = match datastore::get_entry::<Usage>(&data_root.usage(), &user_id.to_string()) {
+            Ok(user_usage) => user_usage,
+            Err(datastore::GetError::NotFound()) => new(user_id),
+            Err(datastore::GetError::Read(err)) => return Err(datastore::AddError::Read(err)),
+        };
+
+        entries.push((user_id.to_string(), add_runs(&user_usage, user_runs, retention)));
+    }
+
+    if !entries.is_empty() {
+        datastore::add_entries(&data_root.usage(), &entries)?;
+    }
+
+    // Runs added while writing are kept for the next flush
+    let mut pending_runs = pending.runs.lock().unwrap();
+
+    for (user_id, user_runs) in &runs {
+        if let Some(current_runs) = pending_runs.get_mut(user_id) {
+            current_runs.drain(..user_runs.len().min(current_runs.len()));
+
+            if current_runs.is_empty() {
+                pending_runs.remove(user_id);
+            }
+        }
+    }
+
+    Ok(runs.values().map(|user_runs| user_runs.len()).sum())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RETENTION: time::Duration = time::Duration::from_secs(365 * 24 * 60 * 60);
+
+    fn counters(wall_time_ms: u64) -> Counters {
+        Counters{
+            runs: 1,
+            wall_time_ms,
+            input_bytes: 10,
+            output_bytes: 20,
+        }
+    }
+
+    fn data_root_with_user(dir: &tempfile::TempDir) -> (config::DataRoot, user::User) {
+        let data_root = config::test_data_root(dir.path());
+        let user = user::test_user(vec![]);
+
+        datastore::init(&data_root.users()).ok().unwrap();
+        datastore::init(&data_root.usage()).ok().unwrap();
+        datastore::add_entry(&data_root.users(), &user.id.to_string(), &user).ok().unwrap();
+
+        (data_root, user)
+    }
+
+    fn stored_usage(data_root: &config::DataRoot, user_id: &uuid::Uuid) -> Option<Usage> {
+        datastore::get_entry::<Usage>(&data_root.usage(), &user_id.to_string()).ok()
+    }
+
+    #[test]
+    fn apply_adds_pending_runs_of_the_user() {
+        let pending = Pending::default();
+        let user_id = uuid::Uuid::new_v4();
+        let now = time::SystemTime::now();
+
+        pending.add(&user_id, now, &counters(100));
+        pending.add(&user_id, now, &counters(200));
+        pending.add(&uuid::Uuid::new_v4(), now, &counters(400));
+
+        let user_usage = pending.apply(&new(&user_id), RETENTION);
+        let day = &user_usage.days[&util::date(now)];
+
+        assert_eq!(day.runs, 2);
+        assert_eq!(day.wall_time_ms, 300);
+        assert_eq!(user_usage.quota_usage.daily.runs, 2);
+        assert_eq!(user_usage.quota_usage.monthly.wall_time_ms, 300);
+    }
+
+    #[test]
+    fn take_removes_pending_runs() {
+        let pending = Pending::default();
+        let user_id = uuid::Uuid::new_v4();
+
+        pending.add(&user_id, time::SystemTime::now(), &counters(100));
+
+        assert_eq!(pending.take(&user_id).len(), 1);
+        assert!(pending.take(&user_id).is_empty());
+        assert!(pending.apply(&new(&user_id), RETENTION).days.is_empty());
+    }
+
+    #[test]
+    fn flush_writes_pending_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (data_root, user) = data_root_with_user(&dir);
+        let pending = Pending::default();
+        let now = time::SystemTime::now();
+
+        pending.add(&user.id, now, &counters(100));
+        assert_eq!(flush(&data_root, &pending, RETENTION).ok(), Some(1));
+
+        pending.add(&user.id, now, &counters(200));
+        assert_eq!(flush(&data_root, &pending, RETENTION).ok(), Some(1));
+
+        let user_usage = stored_usage(&data_root, &user.id).unwrap();
+        assert_eq!(user_usage.days[&util::date(now)].runs, 2);
+        assert_eq!(user_usage.days[&util::date(now)].wall_time_ms, 300);
+
+        // Flushed runs are not counted again
+        assert_eq!(pending.apply(&user_usage, RETENTION).quota_usage.daily.runs, 2);
+        assert_eq!(flush(&data_root, &pending, RETENTION).ok(), Some(0));
+    }
+
+    #[test]
+    fn flush_drops_runs_of_deleted_users() {
+        let dir = tempfile::tempdir().unwrap();
+        let (data_root, user) = data_root_with_user(&dir);
+        let pending = Pending::default();
+        let deleted_user_id = uuid::Uuid::new_v4();
+
+        pending.add(&user.id, time::SystemTime::now(), &counters(100));
+        pending.add(&deleted_user_id, time::SystemTime::now(), &counters(100));
+
+        assert_eq!(flush(&data_root, &pending, RETENTION).ok(), Some(2));
+        assert!(stored_usage(&data_root, &user.id).is_some());
+        assert!(stored_usage(&data_root, &deleted_user_id).is_none());
+        assert!(pending.take(&deleted_user_id).is_empty());
+    }
+}
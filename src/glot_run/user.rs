@@ -1,36 +1,615 @@
 use std::time;
+use std::collections::BTreeMap;
 
 use crate::glot_run::util;
+use crate::glot_run::rate_limit;
+use crate::glot_run::quota;
+use crate::glot_run::token;
+use crate::glot_run::language;
+use crate::glot_run::storage;
+use crate::glot_run::migration;
 
 
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct User {
     pub id: uuid::Uuid,
-    pub token: ascii::AsciiString,
+    pub name: Option<String>,
+    pub contact: Option<String>,
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub tokens: Vec<Token>,
+    pub rate_limit: Option<rate_limit::RateLimit>,
+    pub max_concurrent_runs: Option<u32>,
+    pub quota: Option<quota::Quota>,
+    pub languages: Option<Vec<String>>,
+    pub suspension: Option<Suspension>,
+    // Incremented on every change made through the admin api
+    pub revision: u64,
     pub created: String,
     pub modified: String,
 }
 
 
-pub fn new(token: &ascii::AsciiString) -> User {
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Suspension {
+    pub reason: String,
+    pub since: String,
+}
+
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Token {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub hash: String,
+    pub expires: Option<String>,
+    pub last_used: Option<String>,
+    pub created: String,
+}
+
+
+// A user as returned by the api, token hashes are only stored
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicUser<'a> {
+    pub id: &'a uuid::Uuid,
+    pub name: &'a Option<String>,
+    pub contact: &'a Option<String>,
+    pub notes: &'a Option<String>,
+    pub labels: &'a BTreeMap<String, String>,
+    pub tokens: Vec<PublicToken<'a>>,
+    pub rate_limit: &'a Option<rate_limit::RateLimit>,
+    pub max_concurrent_runs: &'a Option<u32>,
+    pub quota: &'a Option<quota::Quota>,
+    pub languages: &'a Option<Vec<String>>,
+    pub suspension: &'a Option<Suspension>,
+    pub revision: u64,
+    pub created: &'a str,
+    pub modified: &'a str,
+}
+
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicToken<'a> {
+    pub id: &'a uuid::Uuid,
+    pub name: &'a str,
+    pub expires: &'a Option<String>,
+    pub last_used: &'a Option<String>,
+    pub created: &'a str,
+}
+
+pub const DEFAULT_TOKEN_NAME: &str = "default";
+
+// Index of users by the hashes of their tokens
+pub const TOKEN_HASH_INDEX: storage::Index = storage::Index{
+    name: "tokenHash",
+    keys: token_hashes,
+};
+
+// How often the last used timestamp of a token is written to the datastore
+const TOKEN_LAST_USED_INTERVAL: time::Duration = time::Duration::from_secs(60);
+
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserData {
+    pub name: Option<String>,
+    pub contact: Option<String>,
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub rate_limit: Option<rate_limit::RateLimit>,
+    pub max_concurrent_runs: Option<u32>,
+    pub quota: Option<quota::Quota>,
+    pub languages: Option<Vec<String>>,
+}
+
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateData {
+    #[serde(default, deserialize_with = "util::deserialize_nullable")]
+    pub name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "util::deserialize_nullable")]
+    pub contact: Option<Option<String>>,
+    #[serde(default, deserialize_with = "util::deserialize_nullable")]
+    pub notes: Option<Option<String>>,
+    pub labels: Option<BTreeMap<String, String>>,
+    #[serde(default, deserialize_with = "util::deserialize_nullable")]
+    pub rate_limit: Option<Option<rate_limit::RateLimit>>,
+    #[serde(default, deserialize_with = "util::deserialize_nullable")]
+    pub max_concurrent_runs: Option<Option<u32>>,
+    #[serde(default, deserialize_with = "util::deserialize_nullable")]
+    pub quota: Option<Option<quota::Quota>>,
+    #[serde(default, deserialize_with = "util::deserialize_nullable")]
+    pub languages: Option<Option<Vec<String>>>,
+    pub suspended: Option<bool>,
+    pub suspended_reason: Option<String>,
+}
+
+
+pub fn new(data: &UserData, token: Token) -> User {
     let id = uuid::Uuid::new_v4();
     let now = time::SystemTime::now();
 
     User{
         id,
-        token: token.clone(),
+        name: data.name.clone(),
+        contact: data.contact.clone(),
+        notes: data.notes.clone(),
+        labels: data.labels.clone(),
+        tokens: vec![token],
+        rate_limit: data.rate_limit,
+        max_concurrent_runs: data.max_concurrent_runs,
+        quota: data.quota,
+        languages: data.languages.clone(),
+        suspension: None,
+        revision: 1,
         created: util::rfc3339(now),
         modified: util::rfc3339(now),
     }
 }
 
-pub fn update_token(user: &User, token: &ascii::AsciiString) -> User {
+pub fn update(user: &User, data: &UpdateData) -> User {
     let now = time::SystemTime::now();
 
     User{
-        token: token.clone(),
+        name: data.name.clone().unwrap_or_else(|| user.name.clone()),
+        contact: data.contact.clone().unwrap_or_else(|| user.contact.clone()),
+        notes: data.notes.clone().unwrap_or_else(|| user.notes.clone()),
+        labels: data.labels.clone().unwrap_or_else(|| user.labels.clone()),
+        rate_limit: data.rate_limit.unwrap_or(user.rate_limit),
+        max_concurrent_runs: data.max_concurrent_runs.unwrap_or(user.max_concurrent_runs),
+        quota: data.quota.unwrap_or(user.quota),
+        languages: data.languages.clone().unwrap_or_else(|| user.languages.clone()),
+        suspension: update_suspension(user, data, now),
+        revision: user.revision + 1,
+        modified: util::rfc3339(now),
+        ..user.clone()
+    }
+}
+
+fn update_suspension(user: &User, data: &UpdateData, now: time::SystemTime) -> Option<Suspension> {
+    match (data.suspended, &user.suspension) {
+        (Some(true), Some(suspension)) => {
+            Some(Suspension{
+                reason: data.suspended_reason.clone().unwrap_or_else(|| suspension.reason.clone()),
+                ..suspension.clone()
+            })
+        }
+
+        (Some(true), None) => {
+            Some(Suspension{
+                reason: data.suspended_reason.clone().unwrap_or_default(),
+                since: util::rfc3339(now),
+            })
+        }
+
+        (Some(false), _) => {
+            None
+        }
+
+        (None, _) => {
+            user.suspension.clone()
+        }
+    }
+}
+
+pub fn to_public(user: &User) -> PublicUser<'_> {
+    PublicUser{
+        id: &user.id,
+        name: &user.name,
+        contact: &user.contact,
+        notes: &user.notes,
+        labels: &user.labels,
+        tokens: user.tokens.iter().map(to_public_token).collect(),
+        rate_limit: &user.rate_limit,
+        max_concurrent_runs: &user.max_concurrent_runs,
+        quota: &user.quota,
+        languages: &user.languages,
+        suspension: &user.suspension,
+        revision: user.revision,
+        created: &user.created,
+        modified: &user.modified,
+    }
+}
+
+pub fn to_public_token(token: &Token) -> PublicToken<'_> {
+    PublicToken{
+        id: &token.id,
+        name: &token.name,
+        expires: &token.expires,
+        last_used: &token.last_used,
+        created: &token.created,
+    }
+}
+
+// The allow-list consists of patterns on the form name or name/version where * is a wildcard
+pub fn can_run(user: &User, language: &language::Language) -> bool {
+    match &user.languages {
+        Some(patterns) => {
+            patterns.iter().any(|pattern| {
+                let (name_pattern, version_pattern) = pattern.split_once('/').unwrap_or((pattern, "*"));
+                util::glob_match(name_pattern, &language.name) && util::glob_match(version_pattern, &language.version)
+            })
+        }
+
+        None => {
+            true
+        }
+    }
+}
+
+// Matches labels on the form key:value, or just key to match any value
+pub fn has_label(user: &User, label: &str) -> bool {
+    match label.split_once(':') {
+        Some((key, value)) => {
+            user.labels.get(key).map(|v| v == value).unwrap_or(false)
+        }
+
+        None => {
+            user.labels.contains_key(label)
+        }
+    }
+}
+
+pub fn new_token(name: &str, hash: String, expires: Option<time::SystemTime>) -> Token {
+    let now = time::SystemTime::now();
+
+    Token{
+        id: uuid::Uuid::new_v4(),
+        name: name.to_string(),
+        hash,
+        expires: expires.map(util::rfc3339),
+        last_used: None,
+        created: util::rfc3339(now),
+    }
+}
+
+pub fn add_token(user: &User, token: Token) -> User {
+    let now = time::SystemTime::now();
+    let mut tokens = user.tokens.clone();
+
+    tokens.push(token);
+
+    User{
+        tokens,
+        revision: user.revision + 1,
+        modified: util::rfc3339(now),
+        ..user.clone()
+    }
+}
+
+pub fn remove_token(user: &User, token_id: &uuid::Uuid) -> User {
+    let now = time::SystemTime::now();
+
+    let tokens = user.tokens.iter()
+        .filter(|token| token.id != *token_id)
+        .cloned()
+        .collect();
+
+    User{
+        tokens,
+        revision: user.revision + 1,
+        modified: util::rfc3339(now),
+        ..user.clone()
+    }
+}
+
+// Adds a new token, existing tokens with the same name keep working until the grace period is over
+pub fn rotate_token(user: &User, token: Token, grace_period: time::Duration) -> User {
+    let now = time::SystemTime::now();
+    let grace_period_end = now + grace_period;
+
+    let mut tokens: Vec<Token> = user.tokens.iter()
+        .filter(|existing| existing.name != token.name || !is_expired(existing, now))
+        .map(|existing| {
+            if existing.name != token.name || is_expired(existing, grace_period_end) {
+                existing.clone()
+            } else {
+                Token{
+                    expires: Some(util::rfc3339(grace_period_end)),
+                    ..existing.clone()
+                }
+            }
+        })
+        .collect();
+
+    tokens.push(token);
+
+    User{
+        tokens,
+        revision: user.revision + 1,
         modified: util::rfc3339(now),
         ..user.clone()
     }
 }
+
+fn token_hashes(entry: &serde_json::Value) -> Vec<String> {
+    entry.get("tokens")
+        .and_then(|tokens| tokens.as_array())
+        .map(|tokens| {
+            tokens.iter()
+                .filter_map(|token| token.get("hash")?.as_str())
+                .map(|hash| hash.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn find_token<'a>(user: &'a User, token_hash: &str) -> Option<&'a Token> {
+    user.tokens.iter().find(|token| {
+        token::hash_eq(&token.hash, token_hash)
+    })
+}
+
+// Returns the updated user if the last used timestamp of the token is outdated
+pub fn token_used(user: &User, token_id: &uuid::Uuid, now: time::SystemTime) -> Option<User> {
+    let token = user.tokens.iter().find(|token| token.id == *token_id)?;
+
+    let is_outdated = token.last_used.as_ref()
+        .and_then(|last_used| util::parse_rfc3339(last_used).ok())
+        .map(|last_used| last_used + TOKEN_LAST_USED_INTERVAL <= now)
+        .unwrap_or(true);
+
+    if !is_outdated {
+        return None
+    }
+
+    let tokens = user.tokens.iter()
+        .map(|token| {
+            if token.id == *token_id {
+                Token{
+                    last_used: Some(util::rfc3339(now)),
+                    ..token.clone()
+                }
+            } else {
+                token.clone()
+            }
+        })
+        .collect();
+
+    Some(User{
+        tokens,
+        ..user.clone()
+    })
+}
+
+pub fn is_expired(token: &Token, now: time::SystemTime) -> bool {
+    match &token.expires {
+        Some(expires) => {
+            // An invalid timestamp is treated as expired
+            util::parse_rfc3339(expires)
+                .map(|expires| expires <= now)
+                .unwrap_or(true)
+        }
+
+        None => {
+            false
+        }
+    }
+}
+
+pub fn migrations(token_hash_key: &ascii::AsciiString) -> Vec<migration::Migration> {
+    let token_hash_key = token_hash_key.clone();
+
+    vec![
+        migration::Migration{
+            version: 1,
+            description: "Replace plaintext token with token hash",
+            migrate: Box::new(move |entry| migrate_plaintext_token(entry, &token_hash_key)),
+        },
+        migration::Migration{
+            version: 2,
+            description: "Replace token hash with token list",
+            migrate: Box::new(migrate_token_hash),
+        },
+        migration::Migration{
+            version: 3,
+            description: "Add token ids and names",
+            migrate: Box::new(migrate_token_names),
+        },
+        migration::Migration{
+            version: 4,
+            description: "Add revision",
+            migrate: Box::new(migration::add_revision),
+        },
+    ]
+}
+
+// Replaces the plaintext token of users created before tokens were hashed
+fn migrate_plaintext_token(entry: &serde_json::Value, token_hash_key: &ascii::AsciiString) -> Option<serde_json::Value> {
+    let token = entry.get("token")?.as_str()?;
+    let mut new_entry = entry.clone();
+    let fields = new_entry.as_object_mut()?;
+
+    fields.remove("token");
+    fields.insert("tokenHash".to_string(), serde_json::Value::String(token::hash(token_hash_key, token)));
+
+    Some(new_entry)
+}
+
+// Replaces the single token hash of users created before users could have multiple tokens
+fn migrate_token_hash(entry: &serde_json::Value) -> Option<serde_json::Value> {
+    let token_hash = entry.get("tokenHash")?.as_str()?;
+    let created = entry.get("created")?.as_str()?;
+    let mut new_entry = entry.clone();
+    let fields = new_entry.as_object_mut()?;
+
+    let token = serde_json::json!({
+        "hash": token_hash,
+        "expires": null,
+        "created": created,
+    });
+
+    fields.remove("tokenHash");
+    fields.insert("tokens".to_string(), serde_json::Value::Array(vec![token]));
+
+    Some(new_entry)
+}
+
+// Adds id, name and last used timestamp to tokens created before users could have multiple named tokens
+fn migrate_token_names(entry: &serde_json::Value) -> Option<serde_json::Value> {
+    let tokens = entry.get("tokens")?.as_array()?;
+
+    if tokens.iter().all(|token| token.get("id").is_some()) {
+        return None
+    }
+
+    let new_tokens = tokens.iter()
+        .map(|token| {
+            let mut new_token = token.clone();
+
+            if let Some(fields) = new_token.as_object_mut() {
+                fields.entry("id").or_insert_with(|| serde_json::json!(uuid::Uuid::new_v4()));
+                fields.entry("name").or_insert_with(|| serde_json::json!(DEFAULT_TOKEN_NAME));
+                fields.entry("lastUsed").or_insert(serde_json::Value::Null);
+            }
+
+            new_token
+        })
+        .collect();
+
+    let mut new_entry = entry.clone();
+    new_entry.as_object_mut()?.insert("tokens".to_string(), serde_json::Value::Array(new_tokens));
+
+    Some(new_entry)
+}
+
+
+// A user with the given tokens and no other settings, for tests
+#[cfg(test)]
+pub(crate) fn test_user(tokens: Vec<Token>) -> User {
+    User{
+        tokens,
+        ..new(&UserData{
+            name: None,
+            contact: None,
+            notes: None,
+            labels: BTreeMap::new(),
+            rate_limit: None,
+            max_concurrent_runs: None,
+            quota: None,
+            languages: None,
+        }, new_token(DEFAULT_TOKEN_NAME, String::new(), None))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(name: &str, hash: &str, expires: Option<time::SystemTime>) -> Token {
+        new_token(name, hash.to_string(), expires)
+    }
+
+    fn find_by_hash<'a>(user: &'a User, hash: &str) -> &'a Token {
+        user.tokens.iter().find(|token| token.hash == hash).unwrap()
+    }
+
+    #[test]
+    fn rotate_token_keeps_old_token_during_grace_period() {
+        let now = time::SystemTime::now();
+        let grace_period = time::Duration::from_secs(3600);
+        let user = test_user(vec![token("ci", "old", None), token("other", "other", None)]);
+
+        let rotated = rotate_token(&user, token("ci", "new", None), grace_period);
+
+        assert_eq!(rotated.tokens.len(), 3);
+        assert_eq!(rotated.revision, user.revision + 1);
+
+        let old_token = find_by_hash(&rotated, "old");
+        assert!(!is_expired(old_token, now));
+        assert!(is_expired(old_token, now + grace_period + time::Duration::from_secs(1)));
+
+        assert!(find_by_hash(&rotated, "other").expires.is_none());
+        assert!(find_by_hash(&rotated, "new").expires.is_none());
+    }
+
+    #[test]
+    fn rotate_token_keeps_earlier_expiry() {
+        let expires = time::SystemTime::now() + time::Duration::from_secs(60);
+        let user = test_user(vec![token("ci", "old", Some(expires))]);
+
+        let rotated = rotate_token(&user, token("ci", "new", None), time::Duration::from_secs(3600));
+
+        assert_eq!(find_by_hash(&rotated, "old").expires, Some(util::rfc3339(expires)));
+    }
+
+    #[test]
+    fn rotate_token_removes_expired_tokens_with_same_name() {
+        let expired = time::SystemTime::now() - time::Duration::from_secs(60);
+        let user = test_user(vec![token("ci", "expired", Some(expired)), token("other", "other", Some(expired))]);
+
+        let rotated = rotate_token(&user, token("ci", "new", None), time::Duration::from_secs(3600));
+
+        let hashes: Vec<&str> = rotated.tokens.iter().map(|token| token.hash.as_str()).collect();
+        assert_eq!(hashes, vec!["other", "new"]);
+    }
+
+    #[test]
+    fn is_expired_treats_invalid_timestamps_as_expired() {
+        let now = time::SystemTime::now();
+
+        assert!(!is_expired(&token("ci", "hash", None), now));
+        assert!(!is_expired(&token("ci", "hash", Some(now + time::Duration::from_secs(60))), now));
+        assert!(is_expired(&token("ci", "hash", Some(now)), now));
+
+        let invalid = Token{
+            expires: Some("tomorrow".to_string()),
+            ..token("ci", "hash", None)
+        };
+        assert!(is_expired(&invalid, now));
+    }
+
+    #[test]
+    fn find_token_matches_hash() {
+        let user = test_user(vec![token("a", "first", None), token("b", "second", None)]);
+
+        assert_eq!(find_token(&user, "second").map(|token| token.name.as_str()), Some("b"));
+        assert!(find_token(&user, "third").is_none());
+    }
+
+    #[test]
+    fn token_used_only_updates_outdated_timestamps() {
+        let now = time::SystemTime::now();
+        let user = test_user(vec![token("ci", "hash", None)]);
+        let token_id = user.tokens[0].id;
+
+        let used = token_used(&user, &token_id, now).unwrap();
+        assert_eq!(used.tokens[0].last_used, Some(util::rfc3339(now)));
+
+        assert!(token_used(&used, &token_id, now + time::Duration::from_secs(1)).is_none());
+        assert!(token_used(&used, &token_id, now + TOKEN_LAST_USED_INTERVAL).is_some());
+        assert!(token_used(&user, &uuid::Uuid::new_v4(), now).is_none());
+    }
+
+    #[test]
+    fn can_run_matches_name_and_version_patterns() {
+        let python = language::test_language("python", "3.9", &[]);
+
+        let allowed = |patterns: Option<&[&str]>| {
+            let user = User{
+                languages: patterns.map(|patterns| patterns.iter().map(|pattern| pattern.to_string()).collect()),
+                ..test_user(vec![])
+            };
+
+            can_run(&user, &python)
+        };
+
+        assert!(allowed(None));
+        assert!(allowed(Some(&["python"])));
+        assert!(allowed(Some(&["ruby", "python/3.*"])));
+        assert!(allowed(Some(&["*/3.9"])));
+        assert!(!allowed(Some(&[])));
+        assert!(!allowed(Some(&["python/2.*"])));
+        assert!(!allowed(Some(&["py"])));
+    }
+}
@@ -6,6 +6,38 @@ pub fn rfc3339(system_time: time::SystemTime) -> String {
     dt.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
 }
 
+pub fn parse_rfc3339(s: &str) -> Result<time::SystemTime, chrono::ParseError> {
+    let dt = chrono::DateTime::parse_from_rfc3339(s)?;
+    Ok(dt.with_timezone(&chrono::Utc).into())
+}
+
+// Sortable timestamp without separators that are problematic in file names
+pub fn compact_timestamp(system_time: time::SystemTime) -> String {
+    let dt: chrono::DateTime<chrono::Utc> = system_time.into();
+    dt.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+pub fn parse_compact_timestamp(s: &str) -> Result<time::SystemTime, chrono::ParseError> {
+    let dt = chrono::NaiveDateTime::parse_from_str(s, "%Y%m%dT%H%M%SZ")?;
+    Ok(chrono::TimeZone::from_utc_datetime(&chrono::Utc, &dt).into())
+}
+
+// Date format used in http headers, i.e. Sunset
+pub fn http_date(system_time: time::SystemTime) -> String {
+    let dt: chrono::DateTime<chrono::Utc> = system_time.into();
+    dt.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+pub fn date(system_time: time::SystemTime) -> String {
+    let dt: chrono::DateTime<chrono::Utc> = system_time.into();
+    dt.format("%Y-%m-%d").to_string()
+}
+
+pub fn month(system_time: time::SystemTime) -> String {
+    let dt: chrono::DateTime<chrono::Utc> = system_time.into();
+    dt.format("%Y-%m").to_string()
+}
+
 
 pub fn err_if_false<E>(value: bool, err: E) -> Result<(), E> {
     if value {
@@ -14,3 +46,77 @@ pub fn err_if_false<E>(value: bool, err: E) -> Result<(), E> {
         Err(err)
     }
 }
+
+
+// Matches a pattern where * matches any sequence of characters
+pub fn glob_match(pattern: &str, s: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, rest)) => {
+            match s.strip_prefix(prefix) {
+                Some(remaining) => {
+                    remaining.char_indices()
+                        .map(|(index, _)| index)
+                        .chain(std::iter::once(remaining.len()))
+                        .any(|index| glob_match(rest, &remaining[index..]))
+                }
+
+                None => {
+                    false
+                }
+            }
+        }
+
+        None => {
+            pattern == s
+        }
+    }
+}
+
+
+// Distinguishes between a missing field (None) and an explicit null (Some(None))
+pub fn deserialize_nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+    where
+        T: serde::Deserialize<'de>,
+        D: serde::Deserializer<'de> {
+
+    serde::Deserialize::deserialize(deserializer).map(Some)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_match_without_wildcard_is_exact() {
+        assert!(glob_match("python", "python"));
+        assert!(!glob_match("python", "python3"));
+        assert!(!glob_match("python", ""));
+    }
+
+    #[test]
+    fn glob_match_wildcard_matches_any_sequence() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "python"));
+        assert!(glob_match("3.*", "3.9"));
+        assert!(glob_match("3.*", "3."));
+        assert!(!glob_match("3.*", "2.7"));
+        assert!(glob_match("*-slim", "3.9-slim"));
+        assert!(!glob_match("*-slim", "3.9-slim-buster"));
+    }
+
+    #[test]
+    fn glob_match_multiple_wildcards() {
+        assert!(glob_match("*.*", "3.9"));
+        assert!(glob_match("a*b*c", "abc"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("a*b*c", "axxcyyb"));
+    }
+
+    #[test]
+    fn glob_match_handles_multibyte_characters() {
+        assert!(glob_match("*ø", "smørbrø"));
+        assert!(glob_match("sm*d", "smørbrød"));
+        assert!(!glob_match("*ø*x", "smørbrød"));
+    }
+}
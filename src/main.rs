@@ -1,13 +1,17 @@
 mod glot_run;
 
+use std::env;
 use std::process;
 use std::thread;
 use std::fs;
 use std::io;
 use std::fmt;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::RwLock;
+use std::time;
 
 use signal_hook::iterator::Signals;
 
@@ -15,16 +19,56 @@ use glot_run::config;
 use glot_run::environment;
 use glot_run::api;
 use glot_run::datastore;
-use glot_run::file;
 use glot_run::user;
-use glot_run::language;
+use glot_run::usage;
+use glot_run::credential;
 use glot_run::run;
+use glot_run::rate_limit;
+use glot_run::concurrency_limit;
+use glot_run::run_log;
+use glot_run::storage;
+use glot_run::util;
+use glot_run::backup;
+use glot_run::migration;
+use glot_run::language;
 
 
 fn main() {
     env_logger::init();
 
-    match start() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+
+    let result = match args.as_slice() {
+        [] => {
+            start()
+        }
+
+        [command, from, to] if command == "migrate-datastore" => {
+            migrate_datastore(from, to)
+        }
+
+        [command] if command == "migrate" => {
+            migrate(false)
+        }
+
+        [command, flag] if command == "migrate" && flag == "--dry-run" => {
+            migrate(true)
+        }
+
+        [command] if command == "list-backups" => {
+            list_backups()
+        }
+
+        [command, name] if command == "restore-backup" => {
+            restore_backup(name)
+        }
+
+        _ => {
+            Err(Error::Usage())
+        }
+    };
+
+    match result {
         Ok(()) => {}
 
         Err(err) => {
@@ -35,10 +79,16 @@ fn main() {
 }
 
 enum Error {
+    Usage(),
+    InvalidBackend(storage::ParseBackendError),
+    SameBackend(),
     BuildConfig(environment::Error),
     CreateServer(io::Error),
     PrepareDataDirectory(io::Error),
-    DatastoreInit(file::WriteJsonError),
+    DatastoreInit(storage::Error),
+    DatastoreMigrate(migration::Error),
+    DatastoreCopy(&'static str, storage::Error),
+    Backup(backup::Error),
     StartServer(api::Error),
     Signal(io::Error),
 }
@@ -46,6 +96,18 @@ enum Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            Error::Usage() => {
+                write!(f, "Usage: glot-run [migrate [--dry-run] | migrate-datastore <json|sqlite> <json|sqlite> | list-backups | restore-backup <name>]")
+            }
+
+            Error::InvalidBackend(err) => {
+                write!(f, "{}", err)
+            }
+
+            Error::SameBackend() => {
+                write!(f, "The datastore backends must be different")
+            }
+
             Error::BuildConfig(err) => {
                 write!(f, "Failed to build config: {}", err)
             }
@@ -62,6 +124,18 @@ impl fmt::Display for Error {
                 write!(f, "Failed to init datastore: {}", err)
             }
 
+            Error::DatastoreMigrate(err) => {
+                write!(f, "Failed to migrate datastore: {}", err)
+            }
+
+            Error::DatastoreCopy(table, err) => {
+                write!(f, "Failed to copy the {} table: {}", table, err)
+            }
+
+            Error::Backup(err) => {
+                write!(f, "Backup error: {}", err)
+            }
+
             Error::StartServer(err) => {
                 write!(f, "Failed to start api server: {}", err)
             }
@@ -78,7 +152,19 @@ fn start() -> Result<(), Error> {
     let config = build_config(&env)
         .map_err(Error::BuildConfig)?;
 
-    prepare_datastore(&config)?;
+    let reports = prepare_datastore(&config, false)?;
+
+    for (table, report) in &reports {
+        for line in describe_migration(table, report, false) {
+            log::info!("{}", line);
+        }
+    }
+
+    // Enforce run log retention
+    prune_run_log(config.clone());
+
+    // Write the usage of runs to the datastore
+    flush_usage_periodically(config.clone());
 
     log::info!("Listening on {} with {} worker threads", config.server.listen_addr_with_port(), config.server.worker_threads);
 
@@ -86,9 +172,8 @@ fn start() -> Result<(), Error> {
         .map_err(Error::CreateServer)?;
 
     let workers = server.start(api::ServerConfig{
-        listen_addr: config.server.listen_addr_with_port(),
         worker_threads: config.server.worker_threads,
-        handler_config: config,
+        handler_config: config.clone(),
         handler: handle_request,
     }).map_err(Error::StartServer)?;
 
@@ -99,6 +184,9 @@ fn start() -> Result<(), Error> {
     // Wait for workers
     workers.wait();
 
+    // Write the usage of the last runs
+    flush_usage(&config);
+
     Ok(())
 }
 
@@ -106,6 +194,9 @@ fn start() -> Result<(), Error> {
 fn handle_request(config: &config::Config, request: &mut tiny_http::Request) -> Result<api::SuccessResponse, api::ErrorResponse> {
     let url = request.url().to_string();
     let path = url
+        .split('?')
+        .next()
+        .unwrap_or("")
         .trim_start_matches('/')
         .trim_end_matches('/')
         .split('/')
@@ -122,7 +213,7 @@ fn handle_request(config: &config::Config, request: &mut tiny_http::Request) ->
         }
 
         (["languages", language], tiny_http::Method::Get) => {
-            api::languages::list_versions::handle(config, request, &language.to_string())
+            api::languages::list_versions::handle(config, request, language)
         }
 
         (["languages", language, version], tiny_http::Method::Post) => {
@@ -136,40 +227,108 @@ fn handle_request(config: &config::Config, request: &mut tiny_http::Request) ->
             api::images::list::handle(config, request)
         }
 
-        (["admin", "users"], tiny_http::Method::Get) => {
+        (["admin", admin_path @ ..], _) => {
+            handle_admin_request(config, request, admin_path)
+        }
+
+        _ => {
+            api::not_found::handle(config, request)
+        }
+    }
+}
+
+fn handle_admin_request(config: &config::Config, request: &mut tiny_http::Request, path: &[&str]) -> Result<api::SuccessResponse, api::ErrorResponse> {
+    // Every admin route is authorized here, before its handler runs
+    let scope = admin_route_scope(path, request.method());
+    let actor = api::check_access_token(config, request, scope)?;
+
+    match (path, request.method()) {
+        (["users"], tiny_http::Method::Get) => {
             api::admin::users::list::handle(config, request)
         }
 
-        (["admin", "users"], tiny_http::Method::Post) => {
-            api::admin::users::create::handle(config, request)
+        (["users"], tiny_http::Method::Post) => {
+            api::admin::users::create::handle(config, request, &actor)
         }
 
-        (["admin", "users", user_id], tiny_http::Method::Get) => {
-            api::admin::users::get::handle(config, request, &user_id.to_string())
+        (["users", user_id], tiny_http::Method::Get) => {
+            api::admin::users::get::handle(config, user_id)
         }
 
-        (["admin", "users", user_id], tiny_http::Method::Put) => {
-            api::admin::users::update::handle(config, request, &user_id.to_string())
+        (["users", user_id], tiny_http::Method::Put) => {
+            api::admin::users::update::handle(config, request, &actor, user_id)
         }
 
-        (["admin", "users", user_id], tiny_http::Method::Delete) => {
-            api::admin::users::delete::handle(config, request, &user_id.to_string())
+        (["users", user_id], tiny_http::Method::Delete) => {
+            api::admin::users::delete::handle(config, request, &actor, user_id)
         }
 
-        (["admin", "languages"], tiny_http::Method::Get) => {
-            api::admin::languages::list::handle(config, request)
+        (["users", user_id, "usage"], tiny_http::Method::Get) => {
+            api::admin::users::usage::handle(config, user_id)
         }
 
-        (["admin", "languages"], tiny_http::Method::Put) => {
-            api::admin::languages::create::handle(config, request)
+        (["users", user_id, "tokens"], tiny_http::Method::Get) => {
+            api::admin::users::tokens::list::handle(config, user_id)
         }
 
-        (["admin", "languages", language_id], tiny_http::Method::Get) => {
-            api::admin::languages::get::handle(config, request, &language_id.to_string())
+        (["users", user_id, "tokens"], tiny_http::Method::Post) => {
+            api::admin::users::tokens::create::handle(config, request, &actor, user_id)
         }
 
-        (["admin", "languages", language_id], tiny_http::Method::Delete) => {
-            api::admin::languages::delete::handle(config, request, &language_id.to_string())
+        (["users", user_id, "tokens", token_id], tiny_http::Method::Delete) => {
+            api::admin::users::tokens::delete::handle(config, &actor, user_id, token_id)
+        }
+
+        (["languages"], tiny_http::Method::Get) => {
+            api::admin::languages::list::handle(config)
+        }
+
+        (["languages"], tiny_http::Method::Put) => {
+            api::admin::languages::create::handle(config, request, &actor)
+        }
+
+        (["languages", language_id], tiny_http::Method::Get) => {
+            api::admin::languages::get::handle(config, language_id)
+        }
+
+        (["languages", language_id], tiny_http::Method::Put) => {
+            api::admin::languages::update::handle(config, request, &actor, language_id)
+        }
+
+        (["languages", language_id], tiny_http::Method::Delete) => {
+            api::admin::languages::delete::handle(config, request, &actor, language_id)
+        }
+
+        (["credentials"], tiny_http::Method::Get) => {
+            api::admin::credentials::list::handle(config)
+        }
+
+        (["credentials"], tiny_http::Method::Post) => {
+            api::admin::credentials::create::handle(config, request, &actor)
+        }
+
+        (["credentials", credential_id], tiny_http::Method::Delete) => {
+            api::admin::credentials::delete::handle(config, &actor, credential_id)
+        }
+
+        (["audit"], tiny_http::Method::Get) => {
+            api::admin::audit::list::handle(config, request)
+        }
+
+        (["export"], tiny_http::Method::Get) => {
+            api::admin::datastore::export::handle(config)
+        }
+
+        (["import"], tiny_http::Method::Post) => {
+            api::admin::datastore::import::handle(config, request, &actor)
+        }
+
+        (["runs"], tiny_http::Method::Get) => {
+            api::admin::runs::list::handle(config, request)
+        }
+
+        (["runs", run_id], tiny_http::Method::Get) => {
+            api::admin::runs::get::handle(config, run_id)
         }
 
         _ => {
@@ -178,9 +337,98 @@ fn handle_request(config: &config::Config, request: &mut tiny_http::Request) ->
     }
 }
 
+fn admin_route_scope(path: &[&str], method: &tiny_http::Method) -> credential::Scope {
+    match (path, method) {
+        (["users"] | ["users", _] | ["users", _, "usage"] | ["users", _, "tokens"], tiny_http::Method::Get) => {
+            credential::Scope::Read
+        }
+
+        (["languages"] | ["languages", _] | ["audit"] | ["runs"], tiny_http::Method::Get) => {
+            credential::Scope::Read
+        }
+
+        (["users", ..], _) => {
+            credential::Scope::ManageUsers
+        }
+
+        (["languages", ..], _) => {
+            credential::Scope::ManageLanguages
+        }
+
+        (["runs", _], tiny_http::Method::Get) => {
+            credential::Scope::ReadRunDetails
+        }
+
+        (["export"], tiny_http::Method::Get) => {
+            credential::Scope::Export
+        }
+
+        (["import"], tiny_http::Method::Post) => {
+            credential::Scope::Import
+        }
+
+        // Credentials and unknown admin routes require the admin access token
+        _ => {
+            credential::Scope::ManageCredentials
+        }
+    }
+}
+
+
+// How often old entries are removed from the run log
+const RUN_LOG_PRUNE_INTERVAL: time::Duration = time::Duration::from_secs(60 * 60);
+
+fn prune_run_log(config: config::Config) {
+    let run_log_config = config.api.run_log.clone();
+
+    if run_log_config.max_entries.is_none() && run_log_config.max_age.is_none() {
+        return
+    }
+
+    thread::spawn(move || {
+        loop {
+            match run_log::prune(&config.api.run_log_file, &run_log_config, time::SystemTime::now()) {
+                Ok(removed) => {
+                    if removed > 0 {
+                        log::info!("Removed {} entries from the run log", removed);
+                    }
+                }
+
+                Err(err) => {
+                    log::error!("Failed to prune run log: {}", err);
+                }
+            }
+
+            thread::sleep(RUN_LOG_PRUNE_INTERVAL);
+        }
+    });
+}
+
+
+// How often the usage of runs is written to the datastore
+const USAGE_FLUSH_INTERVAL: time::Duration = time::Duration::from_secs(10);
+
+fn flush_usage_periodically(config: config::Config) {
+    thread::spawn(move || {
+        loop {
+            thread::sleep(USAGE_FLUSH_INTERVAL);
+            flush_usage(&config);
+        }
+    });
+}
+
+fn flush_usage(config: &config::Config) {
+    // The read lock is enough since flush is the only writer to the usage table that doesn't take the write lock
+    let data_root = config.server.data_root.read().unwrap();
+
+    if let Err(err) = usage::flush(&data_root, &config.api.pending_usage, config.api.usage_retention) {
+        log::error!("Failed to write usage: {}", err);
+    }
+}
+
 
 fn handle_signals(server: api::Server) -> Result<(), io::Error> {
-    let signals = Signals::new(&[
+    let signals = Signals::new([
         signal_hook::SIGTERM,
         signal_hook::SIGINT,
     ])?;
@@ -213,7 +461,8 @@ fn handle_signals(server: api::Server) -> Result<(), io::Error> {
 
 fn build_config(env: &environment::Environment) -> Result<config::Config, environment::Error> {
     let server = build_server_config(env)?;
-    let api = build_api_config(env)?;
+    let run_log_path = server.data_root.read().unwrap().run_log_path();
+    let api = build_api_config(env, run_log_path)?;
     let run = build_run_config(env)?;
 
     Ok(config::Config{
@@ -229,21 +478,68 @@ fn build_server_config(env: &environment::Environment) -> Result<config::ServerC
     let worker_threads = environment::lookup(env, "SERVER_WORKER_THREADS")?;
     let base_url: String = environment::lookup(env, "SERVER_BASE_URL")?;
     let data_root: PathBuf = environment::lookup(env, "SERVER_DATA_ROOT")?;
+    let datastore_backend = environment::lookup_optional(env, "SERVER_DATASTORE")?;
+    let backup_config = build_backup_config(env, &data_root)?;
 
     Ok(config::ServerConfig{
         listen_addr,
         listen_port,
         worker_threads,
         base_url: base_url.trim_end_matches('/').to_string(),
-        data_root: Arc::new(Mutex::new(config::DataRoot::new(data_root))),
+        data_root: Arc::new(RwLock::new(config::DataRoot::new(data_root, datastore_backend.unwrap_or(storage::Backend::JsonFile), backup_config))),
     })
 }
 
-fn build_api_config(env: &environment::Environment) -> Result<api::ApiConfig, environment::Error> {
+fn build_backup_config(env: &environment::Environment, data_root: &Path) -> Result<backup::Config, environment::Error> {
+    let count: Option<usize> = environment::lookup_optional(env, "SERVER_DATASTORE_BACKUPS")?;
+    let interval_seconds: Option<u64> = environment::lookup_optional(env, "SERVER_DATASTORE_BACKUP_INTERVAL_SECONDS")?;
+
+    Ok(backup::Config{
+        dir: data_root.join("backups"),
+        count: count.unwrap_or(10),
+        interval: time::Duration::from_secs(interval_seconds.unwrap_or(60 * 60)),
+    })
+}
+
+fn build_api_config(env: &environment::Environment, run_log_path: PathBuf) -> Result<api::ApiConfig, environment::Error> {
     let admin_access_token = environment::lookup(env, "API_ADMIN_ACCESS_TOKEN")?;
+    let token_hash_key = environment::lookup(env, "API_TOKEN_HASH_KEY")?;
+    let token_prefix = environment::lookup_optional(env, "API_TOKEN_PREFIX")?;
+    let token_rotation_grace_seconds: Option<u64> = environment::lookup_optional(env, "API_TOKEN_ROTATION_GRACE_SECONDS")?;
+    let rate_limit_per_minute: Option<u32> = environment::lookup_optional(env, "API_RATE_LIMIT_PER_MINUTE")?;
+    let rate_limit_burst: Option<u32> = environment::lookup_optional(env, "API_RATE_LIMIT_BURST")?;
+    let max_concurrent_runs: Option<u32> = environment::lookup_optional(env, "API_MAX_CONCURRENT_RUNS")?;
+    let concurrent_runs_wait_seconds: Option<u64> = environment::lookup_optional(env, "API_CONCURRENT_RUNS_WAIT_SECONDS")?;
+    let run_log_mode = environment::lookup_optional(env, "API_RUN_LOG")?;
+    let run_log_max_entries = environment::lookup_optional(env, "API_RUN_LOG_MAX_ENTRIES")?;
+    let run_log_max_age_days: Option<u64> = environment::lookup_optional(env, "API_RUN_LOG_MAX_AGE_DAYS")?;
+    let usage_retention_days: Option<u64> = environment::lookup_optional(env, "API_USAGE_RETENTION_DAYS")?;
+
+    let default_rate_limit = rate_limit_per_minute.map(|requests_per_minute| {
+        rate_limit::RateLimit{
+            requests_per_minute,
+            burst: rate_limit_burst.unwrap_or(requests_per_minute),
+        }
+    });
 
     Ok(api::ApiConfig{
         admin_access_token,
+        token_hash_key,
+        token_prefix: token_prefix.unwrap_or_default(),
+        token_rotation_grace_period: time::Duration::from_secs(token_rotation_grace_seconds.unwrap_or(0)),
+        rate_limiter: Arc::new(Mutex::new(rate_limit::RateLimiter::new(default_rate_limit))),
+        concurrency_limiter: Arc::new(concurrency_limit::ConcurrencyLimiter::new(
+            max_concurrent_runs,
+            time::Duration::from_secs(concurrent_runs_wait_seconds.unwrap_or(0)),
+        )),
+        run_log: run_log::Config{
+            mode: run_log_mode,
+            max_entries: run_log_max_entries,
+            max_age: run_log_max_age_days.map(|days| time::Duration::from_secs(days * 24 * 60 * 60)),
+        },
+        run_log_file: Arc::new(run_log::RunLog::new(run_log_path)),
+        usage_retention: time::Duration::from_secs(usage_retention_days.unwrap_or(365) * 24 * 60 * 60),
+        pending_usage: Arc::new(usage::Pending::default()),
     })
 }
 
@@ -258,18 +554,196 @@ fn build_run_config(env: &environment::Environment) -> Result<run::Config, envir
 }
 
 
-fn prepare_datastore(config: &config::Config) -> Result<(), Error> {
-    let data_root = config.server.data_root.lock().unwrap();
+// Creates missing tables and migrates existing tables to the current schema version.
+// Nothing is created or written when dry_run is set
+fn prepare_datastore(config: &config::Config, dry_run: bool) -> Result<Vec<(&'static str, migration::Report)>, Error> {
+    let data_root = config.server.data_root.write().unwrap();
+
+    if !dry_run {
+        fs::create_dir_all(&*data_root.root_path())
+            .map_err(Error::PrepareDataDirectory)?;
+
+        datastore::init(&data_root.users())
+            .map_err(Error::DatastoreInit)?;
+
+        datastore::init(&data_root.languages())
+            .map_err(Error::DatastoreInit)?;
+
+        datastore::init(&data_root.usage())
+            .map_err(Error::DatastoreInit)?;
+
+        datastore::init(&data_root.credentials())
+            .map_err(Error::DatastoreInit)?;
+    }
+
+    let users_report = migration::run(&data_root.users(), &user::migrations(&config.api.token_hash_key), dry_run)
+        .map_err(Error::DatastoreMigrate)?;
+
+    let languages_report = migration::run(&data_root.languages(), &language::migrations(), dry_run)
+        .map_err(Error::DatastoreMigrate)?;
+
+    Ok(vec![
+        (data_root.users().name(), users_report),
+        (data_root.languages().name(), languages_report),
+    ])
+}
+
+fn describe_migration(table: &str, report: &migration::Report, dry_run: bool) -> Vec<String> {
+    if !report.exists {
+        return vec![format!("Dry run: the {} table does not exist, it would be created at version {}", table, report.to_version)]
+    }
+
+    let mut lines = report.migrations.iter()
+        .map(|(version, description)| format!("Migration {} of the {} table: {}", version, table, description))
+        .collect::<Vec<String>>();
+
+    let summary = if report.to_version == report.from_version {
+        format!("The {} table is up to date at version {}", table, report.from_version)
+    } else if dry_run {
+        format!("Dry run: would migrate the {} table from version {} to {}, updating {} entries", table, report.from_version, report.to_version, report.updated)
+    } else {
+        format!("Migrated the {} table from version {} to {}, updated {} entries", table, report.from_version, report.to_version, report.updated)
+    };
+
+    lines.push(summary);
+    lines
+}
+
+fn migrate(dry_run: bool) -> Result<(), Error> {
+    let env = environment::get_environment();
+    let config = build_config(&env)
+        .map_err(Error::BuildConfig)?;
+
+    let reports = prepare_datastore(&config, dry_run)?;
+
+    for (table, report) in &reports {
+        for line in describe_migration(table, report, dry_run) {
+            println!("{}", line);
+        }
+    }
+
+    Ok(())
+}
+
+
+// Copies all tables from one datastore backend to another, the server should be stopped while this runs
+fn migrate_datastore(from: &str, to: &str) -> Result<(), Error> {
+    let env = environment::get_environment();
+    let data_root: PathBuf = environment::lookup(&env, "SERVER_DATA_ROOT")
+        .map_err(Error::BuildConfig)?;
+
+    let backup_config = build_backup_config(&env, &data_root)
+        .map_err(Error::BuildConfig)?;
+
+    let from_backend: storage::Backend = from.parse()
+        .map_err(Error::InvalidBackend)?;
+
+    let to_backend: storage::Backend = to.parse()
+        .map_err(Error::InvalidBackend)?;
+
+    util::err_if_false(from_backend != to_backend, Error::SameBackend())?;
 
-    fs::create_dir_all(&*data_root.root_path())
-        .map_err(Error::PrepareDataDirectory)?;
+    let from_storage = storage::new(from_backend, &data_root, backup_config.clone(), &config::INDEXES);
+    let to_storage = storage::new(to_backend, &data_root, backup_config, &config::INDEXES);
 
-    datastore::init::<user::User>(&data_root.users_path())
-        .map_err(Error::DatastoreInit)?;
+    for table in config::TABLES.iter() {
+        let count = storage::copy_table(&*from_storage, &*to_storage, table)
+            .map_err(|err| Error::DatastoreCopy(table, err))?;
 
-    datastore::init::<language::Language>(&data_root.languages_path())
-        .map_err(Error::DatastoreInit)?;
+        println!("Copied {} entries from the {} table", count, table);
+    }
 
     Ok(())
 }
 
+fn list_backups() -> Result<(), Error> {
+    let env = environment::get_environment();
+    let data_root: PathBuf = environment::lookup(&env, "SERVER_DATA_ROOT")
+        .map_err(Error::BuildConfig)?;
+
+    let backup_config = build_backup_config(&env, &data_root)
+        .map_err(Error::BuildConfig)?;
+
+    let backups = backup::list(&backup_config)
+        .map_err(Error::Backup)?;
+
+    for backup in backups {
+        println!("{}", backup.name);
+    }
+
+    Ok(())
+}
+
+// Replaces a datastore file with a backup, the server should be stopped while this runs when using sqlite
+fn restore_backup(name: &str) -> Result<(), Error> {
+    let env = environment::get_environment();
+    let data_root: PathBuf = environment::lookup(&env, "SERVER_DATA_ROOT")
+        .map_err(Error::BuildConfig)?;
+
+    let backup_config = build_backup_config(&env, &data_root)
+        .map_err(Error::BuildConfig)?;
+
+    let backup = backup::restore(&backup_config, &data_root, name)
+        .map_err(Error::Backup)?;
+
+    println!("Restored {} from backup {}", backup.file_name, backup.name);
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_http::Method;
+
+    const ROLES: [credential::Role; 3] = [
+        credential::Role::ReadOnly,
+        credential::Role::UserManager,
+        credential::Role::LanguageManager,
+    ];
+
+    // Route, method, required scope and the roles that are allowed to use it
+    fn routes() -> Vec<(Vec<&'static str>, Method, credential::Scope, Vec<credential::Role>)> {
+        use credential::Role::*;
+        use credential::Scope;
+
+        vec![
+            (vec!["users"], Method::Get, Scope::Read, ROLES.to_vec()),
+            (vec!["users"], Method::Post, Scope::ManageUsers, vec![UserManager]),
+            (vec!["users", "id"], Method::Get, Scope::Read, ROLES.to_vec()),
+            (vec!["users", "id"], Method::Put, Scope::ManageUsers, vec![UserManager]),
+            (vec!["users", "id"], Method::Delete, Scope::ManageUsers, vec![UserManager]),
+            (vec!["users", "id", "usage"], Method::Get, Scope::Read, ROLES.to_vec()),
+            (vec!["users", "id", "tokens"], Method::Get, Scope::Read, ROLES.to_vec()),
+            (vec!["users", "id", "tokens"], Method::Post, Scope::ManageUsers, vec![UserManager]),
+            (vec!["users", "id", "tokens", "tokenId"], Method::Delete, Scope::ManageUsers, vec![UserManager]),
+            (vec!["languages"], Method::Get, Scope::Read, ROLES.to_vec()),
+            (vec!["languages"], Method::Put, Scope::ManageLanguages, vec![LanguageManager]),
+            (vec!["languages", "id"], Method::Get, Scope::Read, ROLES.to_vec()),
+            (vec!["languages", "id"], Method::Put, Scope::ManageLanguages, vec![LanguageManager]),
+            (vec!["languages", "id"], Method::Delete, Scope::ManageLanguages, vec![LanguageManager]),
+            (vec!["credentials"], Method::Get, Scope::ManageCredentials, vec![]),
+            (vec!["credentials"], Method::Post, Scope::ManageCredentials, vec![]),
+            (vec!["credentials", "id"], Method::Delete, Scope::ManageCredentials, vec![]),
+            (vec!["audit"], Method::Get, Scope::Read, ROLES.to_vec()),
+            (vec!["export"], Method::Get, Scope::Export, vec![]),
+            (vec!["import"], Method::Post, Scope::Import, vec![]),
+            (vec!["runs"], Method::Get, Scope::Read, ROLES.to_vec()),
+            (vec!["runs", "id"], Method::Get, Scope::ReadRunDetails, vec![]),
+            (vec!["unknown"], Method::Get, Scope::ManageCredentials, vec![]),
+        ]
+    }
+
+    #[test]
+    fn admin_routes_require_scope_for_each_role() {
+        for (path, method, expected_scope, allowed_roles) in routes() {
+            let scope = admin_route_scope(&path, &method);
+            assert_eq!(scope, expected_scope, "{:?} {:?}", method, path);
+
+            for role in ROLES {
+                assert_eq!(credential::has_scope(role, scope), allowed_roles.contains(&role), "{:?} {:?} {:?}", role, method, path);
+            }
+        }
+    }
+}